@@ -1,6 +1,87 @@
+use crate::output::OutputFormat;
+use crate::report::LogFormat;
+
+use std::num::NonZeroUsize;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceKind {
+    /// arxiv.org
+    Arxiv,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Re-fetch metadata of papers already in database
+    Refresh(RefreshArgs),
+    /// Apply pending database migrations and exit
+    Migrate,
+    /// Export the whole corpus to a directory
+    Dump(DumpArgs),
+    /// Load corpus exported by `dump` into an empty database
+    Restore(RestoreArgs),
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DumpArgs {
+    /// Directory to write the dump to, created if it does not exist
+    #[arg(value_name = "DIR")]
+    pub dir: std::path::PathBuf,
+
+    /// Also dump embeddings computed by this model, can be repeated
+    #[arg(long = "embeddings", value_name = "MODEL")]
+    pub embedding_models: Vec<String>,
+
+    /// Number of papers read from database at once
+    #[arg(
+        long,
+        value_name = "PAPERS",
+        default_value_t = 500,
+        value_parser = clap::value_parser!(i64).range(1..)
+    )]
+    pub page_size: i64,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct RestoreArgs {
+    /// Directory with the dump
+    #[arg(value_name = "DIR")]
+    pub dir: std::path::PathBuf,
+
+    /// Number of papers written to database in one transaction
+    #[arg(long, value_name = "PAPERS", default_value = "100")]
+    pub batch_size: NonZeroUsize,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct RefreshArgs {
+    /// Refresh papers with these ids
+    #[arg(long, value_name = "ID", value_delimiter = ',')]
+    pub ids: Option<Vec<i32>>,
+
+    /// Refresh papers fetched more than this many days ago
+    #[arg(long, value_name = "DAYS")]
+    pub older_than: Option<i64>,
+
+    /// Refresh papers with this subject
+    #[arg(long, value_name = "SUBJECT")]
+    pub subject: Option<String>,
+
+    /// Maximum number of papers to refresh
+    #[arg(long, value_name = "PAPERS")]
+    pub limit: Option<i64>,
+}
+
+/// Scrape papers, or run one of the commands
 #[derive(clap::Parser, Debug, Clone)]
 #[command(version)]
 pub struct Config {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Preprint server to scrape
+    #[arg(long, value_enum, global = true, default_value_t = SourceKind::Arxiv)]
+    pub source: SourceKind,
+
     /// Set start page to scraping
     #[arg(short, long, value_name = "PAGE", default_value_t = 0)]
     pub start_page: usize,
@@ -16,4 +97,44 @@ pub struct Config {
     /// Data directory
     #[arg(short, long, value_name = "FILE", default_value = "data")]
     pub data_dir: std::path::PathBuf,
+
+    /// Where to put scraped papers
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Db)]
+    pub output: OutputFormat,
+
+    /// File for jsonl and json outputs, stdout if not set
+    #[arg(long, value_name = "FILE")]
+    pub output_file: Option<std::path::PathBuf>,
+
+    /// Format of progress reporting
+    #[arg(long, value_enum, global = true, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Number of papers downloaded concurrently
+    #[arg(long, value_name = "TASKS", global = true, default_value = "16")]
+    pub fetch_concurrency: NonZeroUsize,
+
+    /// Number of PDFs parsed concurrently
+    #[arg(long, value_name = "TASKS", default_value = "4")]
+    pub extract_concurrency: NonZeroUsize,
+
+    /// Number of batches written to database concurrently
+    #[arg(long, value_name = "TASKS", default_value = "1")]
+    pub store_concurrency: NonZeroUsize,
+
+    /// Maximum number of papers written to database in one transaction
+    #[arg(long, value_name = "PAPERS", default_value = "25")]
+    pub store_batch_size: NonZeroUsize,
+
+    /// Number of papers buffered between pipeline stages
+    #[arg(long, value_name = "PAPERS", default_value = "32")]
+    pub channel_capacity: NonZeroUsize,
+
+    /// Maximum number of open database connections
+    #[arg(long, value_name = "CONNECTIONS", global = true, default_value_t = 10)]
+    pub db_max_connections: u32,
+
+    /// Seconds to wait for a free database connection
+    #[arg(long, value_name = "SECONDS", global = true, default_value_t = 30)]
+    pub db_acquire_timeout: u64,
 }
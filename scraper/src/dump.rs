@@ -0,0 +1,378 @@
+use arxiv_shared::{
+    db,
+    models::{
+        Caption, NewAuthor, NewCaption, NewPaper, NewPaperFull, NewSubject, NewTakedown,
+        PaperEmbedding, PaperFilter, PaperFull, PaperWithAuthors, Takedown,
+    },
+};
+
+use futures::TryStreamExt;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::config::{DumpArgs, RestoreArgs};
+use crate::scraper::{Error, Result};
+
+/// Bumped on incompatible changes of files below
+const FORMAT_VERSION: u32 = 2;
+
+/// Written last, so dump without it is incomplete
+const MANIFEST_FILE: &str = "manifest.json";
+const PAPERS_FILE: &str = "papers.jsonl.zst";
+const EMBEDDINGS_FILE: &str = "embeddings.jsonl.zst";
+/// Tombstones without paper ids, which are meaningless in another database
+const TAKEDOWNS_FILE: &str = "takedowns.jsonl.zst";
+
+/// Lines queued between database and the thread doing file I/O
+const LINES_BUFFER: usize = 256;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Manifest {
+    pub format_version: u32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub papers: usize,
+    /// Models which embeddings are in `embeddings.jsonl.zst`, empty when
+    /// embeddings were not dumped
+    pub embedding_models: Vec<String>,
+    pub embeddings: usize,
+    pub takedowns: usize,
+}
+
+/// Line of `papers.jsonl.zst`
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PaperRecord {
+    #[serde(flatten)]
+    full: PaperFull,
+    body: String,
+    captions: Vec<Caption>,
+}
+
+/// Line of `embeddings.jsonl.zst`. Paper is referred to by url since ids
+/// change on restore
+#[derive(serde::Serialize, serde::Deserialize)]
+struct EmbeddingRecord {
+    url: String,
+    embedding: PaperEmbedding,
+}
+
+impl From<PaperRecord> for NewPaperFull {
+    fn from(record: PaperRecord) -> Self {
+        let PaperFull {
+            paper,
+            authors,
+            subjects,
+        } = record.full;
+
+        NewPaperFull {
+            paper: NewPaper {
+                url: paper.url,
+                arxiv_id: paper.arxiv_id,
+                source: paper.source,
+                status: paper.status,
+                title: paper.title,
+                body: record.body,
+                description: paper.description,
+            },
+            authors: authors
+                .into_iter()
+                .map(|a| NewAuthor { name: a.name })
+                .collect(),
+            subjects: subjects
+                .into_iter()
+                .map(|s| NewSubject { name: s.name })
+                .collect(),
+            captions: record
+                .captions
+                .into_iter()
+                .map(|c| NewCaption {
+                    kind: c.kind,
+                    number: c.number,
+                    text: c.text,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Write every paper with its authors, subjects and captions, tombstones of
+/// taken down papers, and embeddings of `args.embedding_models`, to
+/// zstd-compressed JSON Lines files in `args.dir`
+pub async fn dump(db: &db::DBConnection, args: &DumpArgs) -> Result<Manifest> {
+    tokio::fs::create_dir_all(&args.dir).await?;
+
+    let mut manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        created_at: chrono::Utc::now(),
+        papers: 0,
+        embedding_models: args.embedding_models.clone(),
+        embeddings: 0,
+        takedowns: 0,
+    };
+
+    let mut takedowns_file = LineWriter::create(args.dir.join(TAKEDOWNS_FILE));
+    for takedown in db.get_takedowns().await? {
+        let Takedown {
+            url,
+            arxiv_id,
+            reason,
+            taken_down_at,
+            ..
+        } = takedown;
+        let record = NewTakedown {
+            url,
+            arxiv_id,
+            reason,
+            taken_down_at,
+        };
+        takedowns_file.write(record).await?;
+        manifest.takedowns += 1;
+    }
+    takedowns_file.finish().await?;
+
+    let mut papers_file = LineWriter::create(args.dir.join(PAPERS_FILE));
+    let mut embeddings_file = match args.embedding_models.is_empty() {
+        true => None,
+        false => Some(LineWriter::create(args.dir.join(EMBEDDINGS_FILE))),
+    };
+
+    let mut pages = db
+        .stream_papers(args.page_size)
+        .try_chunks(args.page_size as usize);
+
+    while let Some(page) = pages.try_next().await.map_err(|e| e.1)? {
+        let paper_ids = page.iter().map(|p| p.paper.id).collect::<Vec<_>>();
+        let urls = page
+            .iter()
+            .map(|p| (p.paper.id, p.paper.url.clone()))
+            .collect::<HashMap<_, _>>();
+        let mut subjects = db.get_subjects_for_papers(&paper_ids).await?;
+
+        for PaperWithAuthors {
+            paper,
+            authors,
+            captions,
+        } in page
+        {
+            let (paper, body) = paper.split_body();
+            let record = PaperRecord {
+                full: PaperFull {
+                    subjects: subjects.remove(&paper.id).unwrap_or_default(),
+                    paper,
+                    authors,
+                },
+                body,
+                captions,
+            };
+            papers_file.write(record).await?;
+            manifest.papers += 1;
+        }
+
+        if let Some(embeddings_file) = &mut embeddings_file {
+            for model in &args.embedding_models {
+                for (paper_id, embedding) in db.get_embeddings(model, &paper_ids).await? {
+                    let url = urls[&paper_id].clone();
+                    embeddings_file
+                        .write(EmbeddingRecord { url, embedding })
+                        .await?;
+                    manifest.embeddings += 1;
+                }
+            }
+        }
+    }
+
+    papers_file.finish().await?;
+    if let Some(embeddings_file) = embeddings_file {
+        embeddings_file.finish().await?;
+    }
+
+    tokio::fs::write(
+        args.dir.join(MANIFEST_FILE),
+        serde_json::to_vec_pretty(&manifest)?,
+    )
+    .await?;
+
+    Ok(manifest)
+}
+
+/// Load dump from `args.dir` into an empty database. Papers get new ids and
+/// timestamps, their change history is not part of the dump. Tombstones are
+/// restored first, so taken down papers stay out of the database
+pub async fn restore(db: &db::DBConnection, args: &RestoreArgs) -> Result<Manifest> {
+    let manifest: Manifest =
+        serde_json::from_slice(&tokio::fs::read(args.dir.join(MANIFEST_FILE)).await?)?;
+    if manifest.format_version != FORMAT_VERSION {
+        return Err(Error::Restore(format!(
+            "unsupported format version {}, expected {FORMAT_VERSION}",
+            manifest.format_version
+        )));
+    }
+    if db.count_papers().await? > 0 || !db.get_takedowns().await?.is_empty() {
+        return Err(Error::Restore("database is not empty".to_string()));
+    }
+
+    let mut takedowns_file = LineReader::open(args.dir.join(TAKEDOWNS_FILE));
+    let mut batch = Vec::with_capacity(args.batch_size.get());
+    let mut restored = 0;
+    while let Some(record) = takedowns_file.read().await {
+        batch.push(record?);
+        if batch.len() >= args.batch_size.get() {
+            restored += db.insert_takedowns(std::mem::take(&mut batch)).await?;
+        }
+    }
+    restored += db.insert_takedowns(batch).await?;
+    check_restored("takedowns", restored as usize, manifest.takedowns)?;
+
+    let mut papers_file = LineReader::open(args.dir.join(PAPERS_FILE));
+    let mut batch = Vec::with_capacity(args.batch_size.get());
+    let mut restored = 0;
+    while let Some(record) = papers_file.read().await {
+        let record: PaperRecord = record?;
+        batch.push(record.into());
+        if batch.len() >= args.batch_size.get() {
+            restored += db
+                .insert_papers_full(std::mem::take(&mut batch))
+                .await?
+                .len();
+        }
+    }
+    restored += db.insert_papers_full(batch).await?.len();
+    check_restored("papers", restored, manifest.papers)?;
+
+    if manifest.embedding_models.is_empty() {
+        return Ok(manifest);
+    }
+
+    let paper_ids = db
+        .select_papers(&PaperFilter::default())
+        .await?
+        .into_iter()
+        .map(|p| (p.url, p.id))
+        .collect::<HashMap<_, _>>();
+
+    let mut embeddings_file = LineReader::open(args.dir.join(EMBEDDINGS_FILE));
+    let mut batch = Vec::with_capacity(args.batch_size.get());
+    let mut restored = 0;
+    while let Some(record) = embeddings_file.read().await {
+        let EmbeddingRecord { url, mut embedding } = record?;
+        let Some(&paper_id) = paper_ids.get(&url) else {
+            log::warn!("Skipping embedding of paper {url:?} which was not restored");
+            continue;
+        };
+        embedding.paper_id = paper_id;
+        batch.push(embedding);
+        if batch.len() >= args.batch_size.get() {
+            restored += batch.len();
+            db.upsert_embeddings(std::mem::take(&mut batch)).await?;
+        }
+    }
+    if !batch.is_empty() {
+        restored += batch.len();
+        db.upsert_embeddings(batch).await?;
+    }
+    check_restored("embeddings", restored, manifest.embeddings)?;
+
+    Ok(manifest)
+}
+
+/// Fail when fewer or more records were restored than the manifest lists, e.g.
+/// because a file of the dump was cut short
+fn check_restored(records: &str, restored: usize, dumped: usize) -> Result<()> {
+    if restored != dumped {
+        return Err(Error::Restore(format!(
+            "restored {restored} {records} but dump has {dumped}"
+        )));
+    }
+    Ok(())
+}
+
+/// Compressed JSON Lines file written on a blocking thread, so that
+/// compression and disk writes do not hold up the async runtime
+struct LineWriter<T> {
+    lines: mpsc::Sender<T>,
+    task: JoinHandle<Result<()>>,
+}
+
+impl<T: serde::Serialize + Send + 'static> LineWriter<T> {
+    fn create(path: PathBuf) -> LineWriter<T> {
+        let (lines, mut rx) = mpsc::channel::<T>(LINES_BUFFER);
+        let task = tokio::task::spawn_blocking(move || {
+            let mut file = create_compressed(&path)?;
+            while let Some(value) = rx.blocking_recv() {
+                write_line(&mut file, &value)?;
+            }
+            file.finish()?.flush()?;
+            Ok(())
+        });
+
+        LineWriter { lines, task }
+    }
+
+    async fn write(&mut self, value: T) -> Result<()> {
+        match self.lines.send(value).await {
+            Ok(()) => Ok(()),
+            // NOTE: writing thread stops early only when it fails
+            Err(_) => (&mut self.task).await?,
+        }
+    }
+
+    /// Wait until all lines are written and the file is complete
+    async fn finish(self) -> Result<()> {
+        drop(self.lines);
+        self.task.await?
+    }
+}
+
+/// Compressed JSON Lines file read and parsed on a blocking thread
+struct LineReader<T> {
+    lines: mpsc::Receiver<Result<T>>,
+}
+
+impl<T: serde::de::DeserializeOwned + Send + 'static> LineReader<T> {
+    fn open(path: PathBuf) -> LineReader<T> {
+        let (tx, lines) = mpsc::channel(LINES_BUFFER);
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = read_lines(&path, &tx) {
+                let _ = tx.blocking_send(Err(e));
+            }
+        });
+
+        LineReader { lines }
+    }
+
+    /// Next parsed line, `None` at the end of the file
+    async fn read(&mut self) -> Option<Result<T>> {
+        self.lines.recv().await
+    }
+}
+
+fn read_lines<T: serde::de::DeserializeOwned>(
+    path: &Path,
+    tx: &mpsc::Sender<Result<T>>,
+) -> Result<()> {
+    for line in open_compressed(path)?.lines() {
+        let value = serde_json::from_str(&line?)?;
+        if tx.blocking_send(Ok(value)).is_err() {
+            // NOTE: reader was dropped, e.g. after failing to store a batch
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn create_compressed(path: &Path) -> Result<zstd::Encoder<'static, BufWriter<File>>> {
+    Ok(zstd::Encoder::new(BufWriter::new(File::create(path)?), 0)?)
+}
+
+fn open_compressed(path: &Path) -> Result<impl BufRead> {
+    Ok(BufReader::new(zstd::Decoder::new(File::open(path)?)?))
+}
+
+fn write_line(writer: &mut impl Write, value: &impl serde::Serialize) -> Result<()> {
+    serde_json::to_writer(&mut *writer, value)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::Mutex;
+
+use crate::scraper::Result;
+
+/// Rate limited HTTP client shared by paper sources
+pub struct HttpClient {
+    client: reqwest::Client,
+    last_request: Mutex<std::time::Instant>,
+    burst_count: Mutex<u8>,
+    bytes_downloaded: AtomicU64,
+}
+
+impl HttpClient {
+    pub fn new() -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent("Googlebot")
+            .build()
+            .unwrap();
+
+        Self {
+            client,
+            last_request: Mutex::new(std::time::Instant::now()),
+            burst_count: Mutex::new(0),
+            bytes_downloaded: AtomicU64::new(0),
+        }
+    }
+
+    /// Total size of downloaded response bodies
+    pub fn bytes_downloaded(&self) -> u64 {
+        self.bytes_downloaded.load(Ordering::Relaxed)
+    }
+
+    pub async fn get(&self, url: &str) -> reqwest::Result<reqwest::Response> {
+        const BURST_SIZE: u8 = 4;
+        const MAX_ATTEMPTS: u32 = 5;
+        let mut burst_count = self.burst_count.lock().await;
+        let mut last_request = self.last_request.lock().await;
+
+        let now = std::time::Instant::now();
+
+        let since_last_request = now - *last_request;
+        if *burst_count >= BURST_SIZE {
+            if since_last_request < std::time::Duration::from_secs(1) {
+                tokio::time::sleep(std::time::Duration::from_secs(1) - since_last_request).await;
+            }
+            *burst_count = 0;
+        }
+        *last_request = now;
+        *burst_count += 1;
+
+        drop(burst_count);
+        drop(last_request);
+
+        let mut backoff = std::time::Duration::from_secs(1);
+        let mut attempt = 1;
+        loop {
+            log::trace!("Reqwest: GET {url:?}");
+            match self.client.get(url).send().await {
+                Err(e) if attempt < MAX_ATTEMPTS => {
+                    log::debug!("Reqwest: GET {url:?} failed, retrying in {backoff:?}: {e}");
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                response => return response,
+            }
+        }
+    }
+
+    pub async fn get_text(&self, url: &str) -> Result<String> {
+        let response = self.get(url).await?;
+        let body = response.error_for_status()?.text().await?;
+        self.bytes_downloaded
+            .fetch_add(body.len() as u64, Ordering::Relaxed);
+
+        Ok(body)
+    }
+
+    /// Same as `get_text` but `None` when page does not exist
+    pub async fn get_text_if_found(&self, url: &str) -> Result<Option<String>> {
+        let response = self.get(url).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body = response.error_for_status()?.text().await?;
+        self.bytes_downloaded
+            .fetch_add(body.len() as u64, Ordering::Relaxed);
+
+        Ok(Some(body))
+    }
+
+    pub async fn get_dom(&self, url: &str) -> Result<scraper::Html> {
+        let body = self.get_text(url).await?;
+        let dom = scraper::Html::parse_document(&body);
+
+        Ok(dom)
+    }
+
+    pub async fn get_bytes(&self, url: &str) -> Result<glib::Bytes> {
+        let response = self.get(url).await?;
+        let bytes = response.bytes().await?;
+        self.bytes_downloaded
+            .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+
+        Ok(glib::Bytes::from_owned(bytes))
+    }
+}
@@ -0,0 +1,194 @@
+use arxiv_shared::{db, models::NewPaperFull};
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::pipeline::ExtractedPaper;
+use crate::scraper::Result;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One JSON record per line
+    Jsonl,
+    /// Single JSON array of records
+    Json,
+    /// Store papers in database
+    Db,
+}
+
+/// Scraped paper as written by file outputs
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct PaperRecord {
+    pub paper: PaperFields,
+    pub authors: Vec<String>,
+    pub subjects: Vec<String>,
+    pub captions: Vec<CaptionRecord>,
+    pub stats: ExtractionStats,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct CaptionRecord {
+    /// `figure` or `table`
+    pub kind: String,
+    pub number: String,
+    pub text: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct PaperFields {
+    pub url: String,
+    pub arxiv_id: Option<String>,
+    pub source: String,
+    /// `active`, `withdrawn` or `superseded`
+    pub status: String,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct ExtractionStats {
+    /// `html` or `pdf`
+    pub full_text_format: String,
+    /// Size of downloaded HTML or PDF in bytes
+    pub full_text_bytes: usize,
+    /// Number of pages in PDF
+    pub pages: usize,
+    /// Number of sections in HTML
+    pub sections: usize,
+    /// Number of characters in extracted body
+    pub body_chars: usize,
+    /// Number of figure and table captions found in body
+    pub captions: usize,
+    /// Time spent extracting body in milliseconds
+    pub extraction_ms: u64,
+}
+
+impl From<ExtractedPaper> for PaperRecord {
+    fn from(extracted: ExtractedPaper) -> Self {
+        PaperRecord {
+            paper: PaperFields {
+                url: extracted.paper.url,
+                arxiv_id: extracted.paper.arxiv_id.map(|id| id.to_string()),
+                source: extracted.paper.source,
+                status: extracted.paper.status.to_string(),
+                title: extracted.paper.title,
+                description: extracted.paper.description,
+                body: extracted.paper.body,
+            },
+            authors: extracted.authors.into_iter().map(|a| a.name).collect(),
+            subjects: extracted.subjects.into_iter().map(|s| s.name).collect(),
+            captions: extracted
+                .captions
+                .into_iter()
+                .map(|c| CaptionRecord {
+                    kind: c.kind.to_string(),
+                    number: c.number,
+                    text: c.text,
+                })
+                .collect(),
+            stats: extracted.stats,
+        }
+    }
+}
+
+/// Destination of scraped papers, file writers are locked only while a batch
+/// is written so database writes are never serialized. File writes run on
+/// blocking threads so a slow pipe does not stall runtime workers
+pub enum Sink {
+    Database(db::DBConnection),
+    Jsonl(Arc<Mutex<Box<dyn Write + Send>>>),
+    Json(Arc<Mutex<JsonWriter>>),
+}
+
+pub struct JsonWriter {
+    writer: Box<dyn Write + Send>,
+    written: usize,
+}
+
+impl Sink {
+    pub fn file(format: OutputFormat, path: Option<&std::path::Path>) -> Result<Sink> {
+        let writer: Box<dyn Write + Send> = match path {
+            Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+            None => Box::new(std::io::BufWriter::new(std::io::stdout())),
+        };
+
+        Ok(match format {
+            OutputFormat::Jsonl => Sink::Jsonl(Arc::new(Mutex::new(writer))),
+            OutputFormat::Json => {
+                Sink::Json(Arc::new(Mutex::new(JsonWriter { writer, written: 0 })))
+            }
+            OutputFormat::Db => unreachable!("database sink is not a file"),
+        })
+    }
+
+    /// Write papers, returns urls of written ones. Database skips papers
+    /// which are already stored or were taken down
+    pub async fn write(&self, papers: Vec<ExtractedPaper>) -> Result<HashSet<String>> {
+        let urls = papers.iter().map(|p| p.paper.url.clone()).collect();
+        match self {
+            Sink::Database(db) => {
+                let papers = papers
+                    .into_iter()
+                    .map(|e| NewPaperFull {
+                        paper: e.paper,
+                        authors: e.authors,
+                        subjects: e.subjects,
+                        captions: e.captions,
+                    })
+                    .collect();
+                return Ok(db.insert_papers_full(papers).await?);
+            }
+            Sink::Jsonl(writer) => {
+                write_blocking(writer, move |writer| {
+                    for paper in papers {
+                        serde_json::to_writer(&mut *writer, &PaperRecord::from(paper))?;
+                        writer.write_all(b"\n")?;
+                    }
+                    Ok(())
+                })
+                .await?
+            }
+            Sink::Json(json) => {
+                write_blocking(json, move |JsonWriter { writer, written }| {
+                    for paper in papers {
+                        writer.write_all(if *written == 0 { b"[\n" } else { b",\n" })?;
+                        serde_json::to_writer(&mut *writer, &PaperRecord::from(paper))?;
+                        *written += 1;
+                    }
+                    Ok(())
+                })
+                .await?
+            }
+        }
+
+        Ok(urls)
+    }
+
+    pub async fn finish(&self) -> Result<()> {
+        match self {
+            Sink::Database(_) => {}
+            Sink::Jsonl(writer) => write_blocking(writer, |writer| Ok(writer.flush()?)).await?,
+            Sink::Json(json) => {
+                write_blocking(json, |JsonWriter { writer, written }| {
+                    writer.write_all(if *written == 0 { b"[]\n" } else { b"\n]\n" })?;
+                    Ok(writer.flush()?)
+                })
+                .await?
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Run `write` with locked `writer` on a blocking thread
+async fn write_blocking<W, F>(writer: &Arc<Mutex<W>>, write: F) -> Result<()>
+where
+    W: Send + 'static,
+    F: FnOnce(&mut W) -> Result<()> + Send + 'static,
+{
+    let writer = writer.clone();
+    tokio::task::spawn_blocking(move || write(&mut writer.lock().unwrap())).await?
+}
@@ -0,0 +1,121 @@
+use arxiv_shared::models::{NewAuthor, NewCaption, NewPaper, NewSubject};
+
+use crate::output::ExtractionStats;
+use crate::source::{FullText, PaperMetadata};
+
+use futures::Stream;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Paper which metadata and full text have been downloaded
+pub struct FetchedPaper {
+    pub source: &'static str,
+    pub metadata: PaperMetadata,
+    pub full_text: FullText,
+}
+
+/// Paper ready to be stored
+pub struct ExtractedPaper {
+    pub paper: NewPaper,
+    pub authors: Vec<NewAuthor>,
+    pub subjects: Vec<NewSubject>,
+    pub captions: Vec<NewCaption>,
+    pub stats: ExtractionStats,
+}
+
+/// Throughput counters of a single pipeline stage
+pub struct StageMetrics {
+    name: &'static str,
+    processed: AtomicU64,
+    busy_nanos: AtomicU64,
+    span: Mutex<Option<(Instant, Instant)>>,
+}
+
+impl StageMetrics {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            processed: AtomicU64::new(0),
+            busy_nanos: AtomicU64::new(0),
+            span: Mutex::new(None),
+        }
+    }
+
+    /// Run `task` accounting its duration to this stage
+    pub async fn measure<F: Future>(&self, task: F) -> F::Output {
+        self.measure_batch(1, task).await
+    }
+
+    /// Run `task` processing `size` papers at once
+    pub async fn measure_batch<F: Future>(&self, size: usize, task: F) -> F::Output {
+        let start = Instant::now();
+        let output = task.await;
+        let end = Instant::now();
+
+        self.processed.fetch_add(size as u64, Ordering::Relaxed);
+        self.busy_nanos
+            .fetch_add((end - start).as_nanos() as u64, Ordering::Relaxed);
+
+        let mut span = self.span.lock().unwrap();
+        *span = match *span {
+            Some((first, _)) => Some((first, end)),
+            None => Some((start, end)),
+        };
+
+        output
+    }
+
+    pub fn summary(&self) -> StageSummary {
+        let busy = Duration::from_nanos(self.busy_nanos.load(Ordering::Relaxed));
+        let wall = self
+            .span
+            .lock()
+            .unwrap()
+            .map(|(first, last)| last - first)
+            .unwrap_or_default();
+
+        StageSummary {
+            stage: self.name,
+            processed: self.processed.load(Ordering::Relaxed),
+            wall_ms: wall.as_millis() as u64,
+            busy_ms: busy.as_millis() as u64,
+        }
+    }
+}
+
+/// Throughput of a finished pipeline stage
+#[derive(serde::Serialize, Debug)]
+pub struct StageSummary {
+    pub stage: &'static str,
+    pub processed: u64,
+    /// Time from first started to last finished paper
+    pub wall_ms: u64,
+    /// Total time spent on papers, larger than `wall_ms` with concurrency
+    pub busy_ms: u64,
+}
+
+impl std::fmt::Display for StageSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.processed == 0 {
+            return write!(f, "{:8} no papers processed", self.stage);
+        }
+
+        write!(
+            f,
+            "{:8} {} papers in {:.1?} ({:.2} papers/s, {:.1?} per paper)",
+            self.stage,
+            self.processed,
+            Duration::from_millis(self.wall_ms),
+            self.processed as f64 / (self.wall_ms as f64 / 1000.0).max(f64::EPSILON),
+            Duration::from_millis(self.busy_ms / self.processed),
+        )
+    }
+}
+
+/// Adapt bounded channel receiver into a stream
+pub fn receiver_stream<T>(mut rx: mpsc::Receiver<T>) -> impl Stream<Item = T> + Unpin {
+    futures::stream::poll_fn(move |cx| rx.poll_recv(cx))
+}
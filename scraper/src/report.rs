@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::pipeline::StageSummary;
+use crate::scraper::Error;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Progress bars for interactive terminals
+    Text,
+    /// One JSON event per line on stderr
+    Json,
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    PageScraped {
+        page: usize,
+        url: &'a str,
+        papers: usize,
+    },
+    PageFailed {
+        page: usize,
+        url: &'a str,
+        kind: &'static str,
+        error: String,
+    },
+    PapersQueued {
+        found: usize,
+        queued: usize,
+    },
+    PaperStored {
+        url: &'a str,
+    },
+    PaperSkipped {
+        url: &'a str,
+    },
+    RefreshStarted {
+        papers: usize,
+    },
+    PaperRefreshed {
+        url: &'a str,
+        changes: usize,
+    },
+    PaperFailed {
+        url: &'a str,
+        kind: &'static str,
+        error: String,
+    },
+    RunFinished(&'a RunSummary),
+}
+
+/// Totals of a finished scraping run
+#[derive(serde::Serialize, Debug)]
+pub struct RunSummary {
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: chrono::DateTime<chrono::Utc>,
+    pub duration_ms: u64,
+    pub query: String,
+    pub pages_scraped: usize,
+    /// Listing pages which failed to load, paging stops at the first one
+    pub pages_failed: usize,
+    pub papers_found: usize,
+    pub papers_stored: usize,
+    /// Papers already stored or taken down by the time they were written
+    pub papers_skipped: usize,
+    pub papers_failed: usize,
+    pub bytes_downloaded: u64,
+    pub failures: BTreeMap<&'static str, usize>,
+    pub stages: Vec<StageSummary>,
+}
+
+#[derive(Default)]
+struct Counters {
+    pages_scraped: usize,
+    pages_failed: usize,
+    papers_found: usize,
+    papers_stored: usize,
+    papers_skipped: usize,
+    papers_failed: usize,
+    failures: BTreeMap<&'static str, usize>,
+}
+
+/// Reports scraping progress either to terminal or as JSON events
+pub struct Reporter {
+    format: LogFormat,
+    progress: Mutex<Option<indicatif::ProgressBar>>,
+    counters: Mutex<Counters>,
+}
+
+impl Reporter {
+    pub fn new(format: LogFormat) -> Self {
+        Self {
+            format,
+            progress: Mutex::new(None),
+            counters: Mutex::new(Counters::default()),
+        }
+    }
+
+    fn emit(&self, event: Event) {
+        if self.format == LogFormat::Json {
+            eprintln!("{}", serde_json::to_string(&event).unwrap());
+        }
+    }
+
+    fn start_progress(&self, len: usize) {
+        if self.format != LogFormat::Text {
+            return;
+        }
+
+        let progress = indicatif::ProgressBar::new(len as u64).with_style(
+            indicatif::ProgressStyle::with_template(
+                "{elapsed_precise:.dim} {bar:50.cyan/blue} {pos}/{len}",
+            )
+            .unwrap(),
+        );
+        progress.enable_steady_tick(std::time::Duration::from_millis(100));
+        *self.progress.lock().unwrap() = Some(progress);
+    }
+
+    fn finish_progress(&self) {
+        if let Some(progress) = self.progress.lock().unwrap().take() {
+            progress.finish_and_clear();
+        }
+    }
+
+    fn inc_progress(&self) {
+        if let Some(progress) = self.progress.lock().unwrap().as_ref() {
+            progress.inc(1);
+        }
+    }
+
+    fn println(&self, message: String) {
+        if self.format != LogFormat::Text {
+            return;
+        }
+
+        match self.progress.lock().unwrap().as_ref() {
+            Some(progress) => progress.println(message),
+            None => eprintln!("{message}"),
+        }
+    }
+
+    pub fn start_pages(&self, max_pages: usize) {
+        self.start_progress(max_pages);
+        self.println(format!(
+            "{} Searching for new papers...",
+            console::style("[1/2]").bold().dim()
+        ));
+    }
+
+    pub fn page_scraped(&self, page: usize, url: &str, papers: usize) {
+        self.counters.lock().unwrap().pages_scraped += 1;
+        self.inc_progress();
+        self.emit(Event::PageScraped { page, url, papers });
+    }
+
+    pub fn page_failed(&self, page: usize, url: &str, error: &Error) {
+        log::warn!("Failed to scrape page {page} {url:?}: {error:?}");
+        self.counters.lock().unwrap().pages_failed += 1;
+        self.println(format!(
+            "{} Failed to scrape page {page}: {error}",
+            console::style("✘").red()
+        ));
+        self.emit(Event::PageFailed {
+            page,
+            url,
+            kind: error.kind(),
+            error: error.to_string(),
+        });
+    }
+
+    pub fn start_papers(&self, found: usize, queued: usize) {
+        self.finish_progress();
+        self.counters.lock().unwrap().papers_found = found;
+        self.println(format!(
+            "{} Scrapping {} papers...",
+            console::style("[2/2]").bold().dim(),
+            queued
+        ));
+        self.emit(Event::PapersQueued { found, queued });
+        self.start_progress(queued);
+    }
+
+    pub fn paper_stored(&self, url: &str) {
+        self.counters.lock().unwrap().papers_stored += 1;
+        self.inc_progress();
+        self.emit(Event::PaperStored { url });
+    }
+
+    pub fn paper_skipped(&self, url: &str) {
+        log::debug!("Skipped {url:?}: already stored or taken down");
+        self.counters.lock().unwrap().papers_skipped += 1;
+        self.inc_progress();
+        self.emit(Event::PaperSkipped { url });
+    }
+
+    pub fn start_refresh(&self, papers: usize) {
+        self.counters.lock().unwrap().papers_found = papers;
+        self.println(format!("Refreshing {papers} papers..."));
+        self.emit(Event::RefreshStarted { papers });
+        self.start_progress(papers);
+    }
+
+    pub fn paper_refreshed(&self, url: &str, changes: usize) {
+        self.counters.lock().unwrap().papers_stored += 1;
+        self.inc_progress();
+        self.emit(Event::PaperRefreshed { url, changes });
+    }
+
+    pub fn paper_failed(&self, url: &str, error: &Error) {
+        log::warn!("Failed to scrape {url:?}: {error:?}");
+        {
+            let mut counters = self.counters.lock().unwrap();
+            counters.papers_failed += 1;
+            *counters.failures.entry(error.kind()).or_default() += 1;
+        }
+        self.inc_progress();
+        self.emit(Event::PaperFailed {
+            url,
+            kind: error.kind(),
+            error: error.to_string(),
+        });
+    }
+
+    pub fn finish(
+        &self,
+        query: String,
+        started_at: chrono::DateTime<chrono::Utc>,
+        bytes_downloaded: u64,
+        stages: Vec<StageSummary>,
+    ) -> RunSummary {
+        self.finish_progress();
+
+        let finished_at = chrono::Utc::now();
+        let counters = std::mem::take(&mut *self.counters.lock().unwrap());
+        let summary = RunSummary {
+            started_at,
+            finished_at,
+            duration_ms: (finished_at - started_at).num_milliseconds().max(0) as u64,
+            query,
+            pages_scraped: counters.pages_scraped,
+            pages_failed: counters.pages_failed,
+            papers_found: counters.papers_found,
+            papers_stored: counters.papers_stored,
+            papers_skipped: counters.papers_skipped,
+            papers_failed: counters.papers_failed,
+            bytes_downloaded,
+            failures: counters.failures,
+            stages,
+        };
+
+        match self.format {
+            LogFormat::Text => {
+                for stage in &summary.stages {
+                    eprintln!("{}", console::style(stage).dim());
+                }
+                if summary.papers_failed > 0 {
+                    eprintln!(
+                        "{} Failed to scrape {} papers: {:?}",
+                        console::style("✘").red(),
+                        summary.papers_failed,
+                        summary.failures
+                    );
+                }
+            }
+            LogFormat::Json => self.emit(Event::RunFinished(&summary)),
+        }
+
+        summary
+    }
+}
@@ -1,21 +1,26 @@
-use crate::config;
+use crate::config::{self, DumpArgs, RefreshArgs, RestoreArgs, SourceKind};
+use crate::dump;
+use crate::http::HttpClient;
+use crate::output::{ExtractionStats, OutputFormat, Sink};
+use crate::pipeline::{self, ExtractedPaper, FetchedPaper, StageMetrics};
+use crate::report::{Reporter, RunSummary};
+use crate::source::{arxiv::ArxivSource, FullText, PaperSource, Url};
 use arxiv_shared::{
-    db,
-    models::{NewAuthor, NewPaper, NewSubject},
+    arxiv_id, db,
+    models::{CaptionKind, NewCaption, NewPaper, NewScrapeRun, PaperFilter, PaperMetadataUpdate},
 };
 
 use futures_util::StreamExt;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-
-type Url = String;
+use std::collections::HashSet;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::mpsc;
 
 pub struct Scraper {
-    client: reqwest::Client,
     config: config::Config,
-    db: Arc<Mutex<db::DBConnection>>,
-    last_request: Arc<Mutex<std::time::Instant>>,
-    burst_count: Arc<Mutex<u8>>,
+    http: Arc<HttpClient>,
+    source: Box<dyn PaperSource>,
+    db: Option<db::DBConnection>,
+    sink: Sink,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -28,295 +33,746 @@ pub enum Error {
 
     #[error("database error")]
     Database(#[from] db::Error),
+
+    #[error("serialization error")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("extraction task failed")]
+    Extraction(#[from] tokio::task::JoinError),
+
+    #[error("invalid paper identifier")]
+    Identifier(#[from] arxiv_id::ParseError),
+
+    #[error("cannot restore dump: {0}")]
+    Restore(String),
+
+    #[error("no paper metadata on page {0:?}")]
+    Metadata(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-type SharedProgress = Arc<Mutex<indicatif::ProgressBar>>;
+impl Error {
+    /// Short name of the failure used in run summaries
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Network(_) => "network",
+            Error::File(_) => "file",
+            Error::Database(_) => "database",
+            Error::Serialization(_) => "serialization",
+            Error::Extraction(_) => "extraction",
+            Error::Identifier(_) => "identifier",
+            Error::Restore(_) => "restore",
+            Error::Metadata(_) => "metadata",
+        }
+    }
+}
 
 impl Scraper {
     pub async fn new(config: config::Config) -> Result<Scraper> {
-        let client = reqwest::Client::builder()
-            .user_agent("Googlebot")
-            .build()
-            .unwrap();
-        let db = Arc::new(Mutex::new(
-            db::DBConnection::new(&std::env::var("SEARXIV_DATABASE_URL").unwrap()).await?,
-        ));
-        let last_request = Arc::new(Mutex::new(std::time::Instant::now()));
-        let burst_count = Arc::new(Mutex::new(0));
+        let http = Arc::new(HttpClient::new());
+        let source = match config.source {
+            SourceKind::Arxiv => Box::new(ArxivSource::new(http.clone())),
+        };
+        let db = if config.output == OutputFormat::Db || config.command.is_some() {
+            let pool = db::PoolConfig {
+                max_connections: config.db_max_connections,
+                acquire_timeout: std::time::Duration::from_secs(config.db_acquire_timeout),
+                ..Default::default()
+            };
+            let db = db::connect(&std::env::var("SEARXIV_DATABASE_URL").unwrap(), &pool).await?;
+            db.migrate().await?;
+            Some(db)
+        } else {
+            None
+        };
+        let sink = match &db {
+            Some(db) if config.output == OutputFormat::Db => Sink::Database(db.clone()),
+            _ => Sink::file(config.output, config.output_file.as_deref())?,
+        };
 
         Ok(Self {
-            client,
             config,
+            http,
+            source,
             db,
-            last_request,
-            burst_count,
+            sink,
+        })
+    }
+
+    /// Number of papers in database, `None` when scraping to file
+    pub async fn get_total_papers(&self) -> Result<Option<i64>> {
+        match &self.db {
+            Some(db) => Ok(Some(db.count_papers().await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn fetch_paper(&self, url: Url) -> Result<FetchedPaper> {
+        let metadata = self.source.fetch_metadata(&url).await?;
+        let full_text = self.source.fetch_full_text(&metadata).await?;
+
+        Ok(FetchedPaper {
+            source: self.source.name(),
+            metadata,
+            full_text,
         })
     }
 
-    pub async fn get_total_papers(&self) -> Result<i64> {
-        self.db
-            .lock()
-            .await
-            .count_papers()
-            .await
-            .map_err(|e| e.into())
+    async fn store_papers(&self, extracted: Vec<ExtractedPaper>) -> Result<HashSet<String>> {
+        self.sink.write(extracted).await
     }
 
-    async fn get(&self, url: &Url) -> reqwest::Result<reqwest::Response> {
-        const BURST_SIZE: u8 = 4;
-        let mut burst_count = self.burst_count.lock().await;
-        let mut last_request = self.last_request.lock().await;
+    pub async fn scrape(&mut self) -> Result<()> {
+        let started_at = chrono::Utc::now();
+        let start_url = self
+            .source
+            .first_page(self.config.start_page, self.config.papers_per_page);
+        let query = start_url.clone();
+        let reporter = Reporter::new(self.config.log_format);
 
-        let now = std::time::Instant::now();
+        reporter.start_pages(self.config.max_pages);
 
-        let since_last_request = now - *last_request;
-        if *burst_count >= BURST_SIZE {
-            if since_last_request < std::time::Duration::from_secs(1) {
-                std::thread::sleep(std::time::Duration::from_secs(1) - since_last_request);
+        let mut paper_urls = Vec::new();
+        let mut current_url = start_url;
+        for page in 0..self.config.max_pages {
+            let listing = match self.source.list(&current_url).await {
+                Ok(listing) => listing,
+                Err(e) => {
+                    reporter.page_failed(self.config.start_page + page, &current_url, &e);
+                    break;
+                }
+            };
+            reporter.page_scraped(
+                self.config.start_page + page,
+                &current_url,
+                listing.papers.len(),
+            );
+            paper_urls.extend(listing.papers);
+
+            if let Some(next_page_url) = listing.next_page {
+                current_url = next_page_url;
+            } else {
+                break;
             }
-            *burst_count = 0;
         }
-        *last_request = now;
-        *burst_count += 1;
-
-        drop(burst_count);
-        drop(last_request);
-
-        let mut backoff = std::time::Duration::from_secs(1);
-        loop {
-            log::trace!("Reqwest: GET {url:?}");
-            let response = self.client.get(url).send().await;
-            if response.is_err() {
-                // std::thread::sleep(backoff); // TODO
-                backoff *= 2;
-                continue;
-            }
 
-            return response;
+        let papers_found = paper_urls.len();
+        let mut paper_urls_to_download = Vec::new();
+        for paper_url in paper_urls {
+            let exists = match &self.db {
+                Some(db) => db.paper_exists(&paper_url).await?,
+                None => false,
+            };
+            if !exists {
+                paper_urls_to_download.push(paper_url);
+            }
         }
-    }
 
-    async fn get_dom(&self, url: Url) -> Result<scraper::Html> {
-        let response = self.get(&url).await?;
-        let body = response.text().await?;
-        let dom = scraper::Html::parse_document(&body);
+        reporter.start_papers(papers_found, paper_urls_to_download.len());
 
-        Ok(dom)
-    }
+        let fetch_metrics = StageMetrics::new("fetch");
+        let extract_metrics = StageMetrics::new("extract");
+        let store_metrics = StageMetrics::new("store");
 
-    async fn download_pdf(&self, url: Url) -> Result<glib::Bytes> {
-        let response = self.get(&url).await?;
-        Ok(glib::Bytes::from_owned(response.bytes().await?))
-    }
+        let (fetched_tx, fetched_rx) = mpsc::channel(self.config.channel_capacity.get());
+        let (extracted_tx, extracted_rx) = mpsc::channel(self.config.channel_capacity.get());
 
-    pub async fn scrape_paper(&self, url: Url, sp: &SharedProgress) -> Result<()> {
-        let dom = self.get_dom(url.clone()).await?;
+        let scraper = &*self;
+        let (fetch_metrics, extract_metrics, store_metrics) =
+            (&fetch_metrics, &extract_metrics, &store_metrics);
+        let reporter = &reporter;
 
-        let title = select_title(&dom);
-        let description = select_description(&dom);
-
-        let pdf_url = url.replace("abs", "pdf");
-        let pdf_bytes = self.download_pdf(pdf_url).await?;
-        let body = body_from_pdf(&pdf_bytes);
-
-        if body.is_empty() {
-            log::warn!("PDF: empty body {url:?}")
-        }
+        let fetch_stage = async move {
+            let mut fetched = futures::stream::iter(paper_urls_to_download)
+                .map(|url| async move {
+                    let paper = fetch_metrics
+                        .measure(scraper.fetch_paper(url.clone()))
+                        .await;
+                    (url, paper)
+                })
+                .buffer_unordered(scraper.config.fetch_concurrency.get());
+            while let Some((url, paper)) = fetched.next().await {
+                match paper {
+                    Ok(paper) => {
+                        if fetched_tx.send(paper).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => reporter.paper_failed(&url, &e),
+                }
+            }
+        };
 
-        let new_paper = NewPaper {
-            url,
-            title,
-            body,
-            description,
+        let extract_stage = async move {
+            let mut extracted = pipeline::receiver_stream(fetched_rx)
+                .map(|paper| async move {
+                    let url = paper.metadata.url.clone();
+                    let task = tokio::task::spawn_blocking(move || extract_paper(paper));
+                    (url, extract_metrics.measure(task).await)
+                })
+                .buffer_unordered(scraper.config.extract_concurrency.get());
+            while let Some((url, paper)) = extracted.next().await {
+                match paper {
+                    Ok(paper) => {
+                        if extracted_tx.send(paper).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => reporter.paper_failed(&url, &e.into()),
+                }
+            }
         };
-        let authors = select_authors(&dom)?;
-        let subjects = select_subjects(&dom)?;
 
-        let mut db = self.db.lock().await;
-        db.insert_paper_full(new_paper, authors, subjects).await?;
+        let store_stage = pipeline::receiver_stream(extracted_rx)
+            .ready_chunks(scraper.config.store_batch_size.get())
+            .for_each_concurrent(scraper.config.store_concurrency.get(), |batch| async move {
+                let urls = batch
+                    .iter()
+                    .map(|p| p.paper.url.clone())
+                    .collect::<Vec<_>>();
+                let stored = store_metrics
+                    .measure_batch(batch.len(), scraper.store_papers(batch))
+                    .await;
+                for url in &urls {
+                    match &stored {
+                        Ok(stored) if stored.contains(url) => reporter.paper_stored(url),
+                        Ok(_) => reporter.paper_skipped(url),
+                        Err(e) => reporter.paper_failed(url, e),
+                    }
+                }
+            });
+
+        tokio::join!(fetch_stage, extract_stage, store_stage);
+        self.sink.finish().await?;
+
+        let summary = reporter.finish(
+            query,
+            started_at,
+            self.http.bytes_downloaded(),
+            vec![
+                fetch_metrics.summary(),
+                extract_metrics.summary(),
+                store_metrics.summary(),
+            ],
+        );
 
-        sp.lock().await.inc(1);
+        self.save_run(summary).await
+    }
 
+    /// Write contents of the database to a dump in `args.dir`
+    pub async fn dump(&self, args: &DumpArgs) -> Result<()> {
+        let db = self.db.as_ref().expect("dump always connects to database");
+        let manifest = dump::dump(db, args).await?;
+        eprintln!(
+            "Dumped {} papers, {} embeddings and {} takedowns to {:?}",
+            manifest.papers, manifest.embeddings, manifest.takedowns, args.dir
+        );
         Ok(())
     }
 
-    pub async fn scrape_page(&mut self, url: Url) -> Result<(Vec<Url>, Option<String>)> {
-        let home_page = self.get(&url).await?;
-        let body = home_page.text().await?;
-        let dom = scraper::Html::parse_document(&body);
-
-        let paper_link_selector = scraper::Selector::parse(".list-title > a").unwrap();
-        let paper_links = dom
-            .select(&paper_link_selector)
-            .map(|l| l.value().attr("href").unwrap().to_string())
-            .collect::<Vec<Url>>();
-
-        let next_page_selector = scraper::Selector::parse("a.pagination-next").unwrap();
-        let mut next_page_url = None;
-        if let Some(next_page_href) = dom.select(&next_page_selector).next() {
-            let mut next_page = "https://arxiv.org".to_string();
-            let next_page_href = next_page_href.value().attr("href").unwrap();
-            next_page.push_str(next_page_href);
-
-            next_page_url = Some(next_page);
-        }
-
-        Ok((paper_links, next_page_url))
+    /// Load dump from `args.dir` into an empty database
+    pub async fn restore(&self, args: &RestoreArgs) -> Result<()> {
+        let db = self
+            .db
+            .as_ref()
+            .expect("restore always connects to database");
+        let manifest = dump::restore(db, args).await?;
+        eprintln!(
+            "Restored dump of {} papers, {} embeddings and {} takedowns made at {}",
+            manifest.papers, manifest.embeddings, manifest.takedowns, manifest.created_at
+        );
+        Ok(())
     }
 
-    pub async fn scrape(&mut self, start_url: Url) -> Result<()> {
-        let pages_progress = indicatif::ProgressBar::new(self.config.max_pages as u64)
-            .with_style(
-                indicatif::ProgressStyle::with_template(
-                    "{elapsed_precise:.dim} {bar:50.cyan/blue} {pos}/{len}",
-                )
-                .unwrap(),
-            )
-            .with_message("Scrapping pages...");
-        pages_progress.enable_steady_tick(std::time::Duration::from_millis(100));
+    /// Re-fetch metadata of papers matching `args` and write differences back
+    pub async fn refresh(&self, args: &RefreshArgs) -> Result<()> {
+        let started_at = chrono::Utc::now();
+        let db = self
+            .db
+            .as_ref()
+            .expect("refresh always connects to database");
+        let reporter = Reporter::new(self.config.log_format);
+
+        let filter = PaperFilter {
+            ids: args.ids.clone(),
+            fetched_before: args
+                .older_than
+                .map(|days| started_at - chrono::Duration::days(days)),
+            subject: args.subject.clone(),
+            limit: args.limit,
+        };
+        let papers = db.select_papers(&filter).await?;
+
+        reporter.start_refresh(papers.len());
+
+        let fetch_metrics = StageMetrics::new("fetch");
+        let store_metrics = StageMetrics::new("store");
+
+        let (fetch_metrics, store_metrics) = (&fetch_metrics, &store_metrics);
+        let reporter = &reporter;
+
+        futures::stream::iter(papers)
+            .map(|paper| async {
+                let metadata = fetch_metrics
+                    .measure(self.source.fetch_metadata(&paper.url))
+                    .await;
+                (paper, metadata)
+            })
+            .buffer_unordered(self.config.fetch_concurrency.get())
+            .for_each(|(paper, metadata)| async move {
+                let changes = match metadata {
+                    Ok(metadata) => {
+                        let update = PaperMetadataUpdate {
+                            title: metadata.title,
+                            description: metadata.description,
+                            status: metadata.status,
+                            authors: metadata.authors,
+                            subjects: metadata.subjects,
+                        };
+                        store_metrics
+                            .measure(db.update_paper_metadata(paper.id, update))
+                            .await
+                            .map_err(Error::from)
+                    }
+                    Err(e) => Err(e),
+                };
+
+                match changes {
+                    Ok(changes) => reporter.paper_refreshed(&paper.url, changes.len()),
+                    Err(e) => reporter.paper_failed(&paper.url, &e),
+                }
+            })
+            .await;
+
+        let summary = reporter.finish(
+            format!("refresh {args:?}"),
+            started_at,
+            self.http.bytes_downloaded(),
+            vec![fetch_metrics.summary(), store_metrics.summary()],
+        );
 
-        pages_progress.println(format!(
-            "{} Searching for new papers...",
-            console::style("[1/2]").bold().dim()
-        ));
+        self.save_run(summary).await
+    }
 
-        let mut paper_urls = Vec::new();
-        let mut current_url = start_url;
-        for _ in 0..self.config.max_pages {
-            let (page_paper_urls, next_page_url) =
-                self.scrape_page(current_url.to_string()).await?;
-            paper_urls.extend(page_paper_urls.into_iter());
+    async fn save_run(&self, summary: RunSummary) -> Result<()> {
+        if let Some(db) = &self.db {
+            db.insert_scrape_run(NewScrapeRun {
+                started_at: summary.started_at,
+                finished_at: summary.finished_at,
+                query: summary.query,
+                pages_scraped: summary.pages_scraped as i32,
+                papers_found: summary.papers_found as i32,
+                papers_stored: summary.papers_stored as i32,
+                papers_failed: summary.papers_failed as i32,
+                bytes_downloaded: summary.bytes_downloaded as i64,
+                failures: summary
+                    .failures
+                    .into_iter()
+                    .map(|(kind, count)| (kind.to_string(), count as i32))
+                    .collect(),
+            })
+            .await?;
+        }
 
-            if let Some(next_page_url) = next_page_url {
-                current_url = next_page_url;
-            } else {
-                break;
-            }
-            pages_progress.inc(1);
+        Ok(())
+    }
+}
+
+fn extract_paper(fetched: FetchedPaper) -> ExtractedPaper {
+    let FetchedPaper {
+        source,
+        metadata,
+        full_text,
+    } = fetched;
+
+    let start = std::time::Instant::now();
+    let (body, pages, sections) = match &full_text {
+        FullText::Pdf(pdf) => {
+            let (body, pages) = body_from_pdf(pdf);
+            (body, pages, 0)
         }
+        FullText::Html(html) => {
+            let (body, sections) = body_from_html(html);
+            (body, 0, sections)
+        }
+    };
+    if body.is_empty() {
+        log::warn!("{}: empty body {:?}", full_text.format(), metadata.url)
+    }
 
-        let mut paper_urls_to_download = Vec::new();
-        for paper_url in paper_urls {
-            let export_url = paper_url.replace("arxiv.org", "export.arxiv.org");
-            if !self.db.lock().await.paper_exists(&export_url).await? {
-                paper_urls_to_download.push(export_url);
+    let captions = captions_from_text(&body);
+
+    let stats = ExtractionStats {
+        full_text_format: full_text.format().to_string(),
+        full_text_bytes: full_text.size(),
+        pages,
+        sections,
+        body_chars: body.chars().count(),
+        captions: captions.len(),
+        extraction_ms: start.elapsed().as_millis() as u64,
+    };
+
+    ExtractedPaper {
+        paper: NewPaper {
+            url: metadata.url,
+            arxiv_id: metadata.arxiv_id,
+            source: source.to_string(),
+            status: metadata.status,
+            title: metadata.title,
+            body,
+            description: metadata.description,
+        },
+        authors: metadata.authors,
+        subjects: metadata.subjects,
+        captions,
+        stats,
+    }
+}
+
+/// Extract text from PDF, returns text and number of pages
+fn body_from_pdf(bytes: &glib::Bytes) -> (String, usize) {
+    let mut body = String::new();
+    let mut pages = 0;
+    if let Ok(pdf) = poppler::Document::from_bytes(bytes, None) {
+        let n = pdf.n_pages();
+        pages = n as usize;
+        for i in 0..n {
+            if let Some(text) = pdf.page(i).and_then(|page| page.text()) {
+                body.push_str(text.as_str());
+                body.push(' ');
             }
         }
+    }
 
-        drop(pages_progress);
+    (fix_line_breaks(body), pages)
+}
 
-        println!(
-            "{} Scrapping {} papers...",
-            console::style("[2/2]").bold().dim(),
-            paper_urls_to_download.len()
-        );
+/// Extract text from LaTeXML rendering, returns text and number of sections.
+/// Headings, paragraphs and captions become paragraphs separated by empty
+/// lines, headings are marked Markdown-style with their level (`## 3 Method`,
+/// `### 3.1 Data`) and formulas are replaced with their LaTeX source
+fn body_from_html(html: &str) -> (String, usize) {
+    let dom = scraper::Html::parse_document(html);
+    let block_selector = scraper::Selector::parse(
+        "article .ltx_title, article p.ltx_p, article figcaption, article .ltx_bibitem",
+    )
+    .unwrap();
+    let section_selector = scraper::Selector::parse("article .ltx_title_section").unwrap();
+
+    let blocks = dom
+        .select(&block_selector)
+        .filter_map(|block| {
+            let mut text = String::new();
+            push_html_text(block, &mut text);
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            match heading_level(block) {
+                _ if text.is_empty() => None,
+                Some(level) => Some(format!("{} {text}", "#".repeat(level))),
+                None => Some(text),
+            }
+        })
+        .collect::<Vec<_>>();
+    let sections = dom.select(&section_selector).count();
 
-        let total_progress = indicatif::ProgressBar::new(paper_urls_to_download.len() as u64)
-            .with_style(
-                indicatif::ProgressStyle::with_template(
-                    "{elapsed_precise:.dim} {bar:50.cyan/blue} {pos}/{len}",
-                )
-                .unwrap(),
-            );
-        total_progress.enable_steady_tick(std::time::Duration::from_millis(100));
+    (blocks.join("\n\n"), sections)
+}
 
-        let amtp = Arc::new(Mutex::new(total_progress));
+/// Level of LaTeXML heading, 1 for document title and 2 for sections,
+/// `None` if element is not a heading
+fn heading_level(element: scraper::ElementRef) -> Option<usize> {
+    let mut classes = element.value().classes();
+    if !classes.any(|c| c == "ltx_title") {
+        return None;
+    }
 
-        let paper_futures = paper_urls_to_download
-            .into_iter()
-            .map(|url| self.scrape_paper(url, &amtp));
-        let stream = futures::stream::iter(paper_futures)
-            .buffer_unordered(25)
-            .collect::<Vec<_>>();
+    let level = element
+        .value()
+        .classes()
+        .find_map(|class| match class {
+            "ltx_title_document" => Some(1),
+            "ltx_title_subsection" => Some(3),
+            "ltx_title_subsubsection" | "ltx_title_paragraph" => Some(4),
+            _ => None,
+        })
+        .unwrap_or(2);
 
-        stream.await.into_iter().collect::<Result<Vec<_>>>()?;
+    Some(level)
+}
 
-        Ok(())
+fn push_html_text(element: scraper::ElementRef, text: &mut String) {
+    for child in element.children() {
+        if let Some(child_text) = child.value().as_text() {
+            text.push_str(child_text);
+        } else if let Some(child) = scraper::ElementRef::wrap(child) {
+            if child.value().name() == "math" {
+                text.push(' ');
+                text.push_str(child.value().attr("alttext").unwrap_or_default());
+                text.push(' ');
+            } else {
+                push_html_text(child, text);
+            }
+        }
     }
 }
 
-fn select_title(dom: &scraper::Html) -> String {
-    let title_selector = scraper::Selector::parse("h1.title").unwrap();
-    dom.select(&title_selector)
-        .next()
-        .map(|el| {
-            el.text()
-                .collect::<String>()
-                .trim()
-                .trim_start_matches("Title:")
-                .trim_start()
-                .replace("  ", " ")
-                .to_string()
-        })
-        .unwrap_or_default()
+/// Longest caption kept, longer ones are cut at the last sentence end
+const MAX_CAPTION_CHARS: usize = 1000;
+
+/// Start of a figure or table caption such as `Figure 3: ...` or `Table A.1. ...`
+fn caption_regex() -> &'static regex::Regex {
+    static CAPTION: OnceLock<regex::Regex> = OnceLock::new();
+    CAPTION.get_or_init(|| {
+        regex::Regex::new(
+            r"(?m)^[^\S\n]*(Figure|Fig\.|Table)\s+([A-Z]?\d+(?:\.\d+)*[a-z]?)\s*[:.|]\s*(\S.*)$",
+        )
+        .unwrap()
+    })
 }
 
-fn select_description(dom: &scraper::Html) -> String {
-    let description_selector = scraper::Selector::parse("blockquote.abstract").unwrap();
-    dom.select(&description_selector)
-        .next()
-        .map(|el| {
-            el.text()
-                .collect::<String>()
-                .trim()
-                .trim_start_matches("Abstract:")
-                .trim_start()
-                .replace('\n', " ")
-                .to_string()
+/// Whether line looks like a section heading, e.g. `3.1 Results` or `REFERENCES`
+fn is_heading(line: &str) -> bool {
+    static HEADING: OnceLock<regex::Regex> = OnceLock::new();
+    HEADING
+        .get_or_init(|| {
+            regex::Regex::new(
+                r"^(?:(?:\d+(?:\.\d+)*\.?|[IVX]+\.)\s+[A-Z][^.]{0,80}|(?i:abstract|introduction|related work|conclusions?|references|acknowledge?ments?|appendix))$",
+            )
+            .unwrap()
         })
-        .unwrap_or_default()
+        .is_match(line)
 }
 
-fn select_authors(dom: &scraper::Html) -> Result<Vec<NewAuthor>> {
-    let authors_selector = scraper::Selector::parse(".authors > a").unwrap();
-    let authors_elements = dom.select(&authors_selector).collect::<Vec<_>>();
-    let authors = authors_elements
-        .iter()
-        .map(|a| NewAuthor {
-            name: a.text().collect::<String>(),
-        })
-        .collect::<Vec<_>>();
+/// Find figure and table captions. Caption ends with the first line ending a
+/// sentence, or before an empty line, a heading or the next caption, so it
+/// works both for PDF text without empty lines and for HTML paragraphs.
+/// References in text wrapped to the start of a line are skipped, and only
+/// first caption with a given number is kept
+fn captions_from_text(text: &str) -> Vec<NewCaption> {
+    let mut seen = HashSet::new();
+    let mut captions = Vec::new();
+    for m in caption_regex().captures_iter(text) {
+        let kind = match &m[1] {
+            "Table" => CaptionKind::Table,
+            _ => CaptionKind::Figure,
+        };
+        let number = m[2].to_string();
+        if seen.contains(&(kind, number.clone()))
+            || continues_sentence(&text[..m.get(0).unwrap().start()])
+        {
+            continue;
+        }
+
+        let start = m.get(3).unwrap().start();
+        let Some(caption) = caption_at(&text[start..]) else {
+            continue;
+        };
 
-    Ok(authors)
+        seen.insert((kind, number.clone()));
+        captions.push(NewCaption {
+            kind,
+            number,
+            text: caption,
+        });
+    }
+
+    captions
 }
 
-fn select_subjects(dom: &scraper::Html) -> Result<Vec<NewSubject>> {
-    let subjects_selector = scraper::Selector::parse("td.subjects").unwrap();
-    let subjects = dom
-        .select(&subjects_selector)
-        .next()
-        .map(|s| {
-            s.text()
-                .collect::<String>()
-                .split(';')
-                .map(|x| NewSubject {
-                    name: x.trim().to_string(),
-                })
-                .collect::<Vec<_>>()
-        })
-        .unwrap_or_default();
+/// Whether line after `before` continues its sentence, e.g. `shown in` followed
+/// by `Table 2. Our method ...`
+fn continues_sentence(before: &str) -> bool {
+    const LEADING_WORDS: [&str; 12] = [
+        "in", "see", "of", "and", "to", "from", "by", "with", "on", "as", "cf.", "the",
+    ];
 
-    Ok(subjects)
+    let last_word = before
+        .trim_end()
+        .rsplit(char::is_whitespace)
+        .next()
+        .unwrap_or("");
+    last_word.ends_with(',') || LEADING_WORDS.contains(&last_word.to_lowercase().as_str())
 }
 
-fn body_from_pdf(bytes: &glib::Bytes) -> String {
-    let mut body = String::new();
-    if let Ok(pdf) = poppler::Document::from_bytes(bytes, None) {
-        let n = pdf.n_pages();
-        for i in 0..n {
-            if let Some(text) = pdf.page(i).and_then(|page| page.text()) {
-                body.push_str(text.as_str());
-                body.push(' ');
-            }
+/// Caption text starting at the beginning of `text`, `None` if it does not
+/// end a sentence within [`MAX_CAPTION_CHARS`]
+fn caption_at(text: &str) -> Option<String> {
+    let mut lines = Vec::new();
+    let mut chars = 0;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if i > 0 && (line.is_empty() || is_heading(line) || caption_regex().is_match(line)) {
+            break;
+        }
+        lines.push(line);
+        chars += line.chars().count() + 1;
+        if line.ends_with('.') || chars > MAX_CAPTION_CHARS {
+            break;
         }
     }
 
-    fix_line_breaks(body)
+    let caption = lines
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if caption.chars().count() <= MAX_CAPTION_CHARS {
+        return Some(caption);
+    }
+
+    let cut = caption
+        .char_indices()
+        .nth(MAX_CAPTION_CHARS)
+        .map_or(caption.len(), |(i, _)| i);
+    let end = caption[..cut].rfind(". ")? + 1;
+    Some(caption[..end].to_string())
 }
 
 fn fix_line_breaks(text: String) -> String {
     let rg = regex::Regex::new(r"(\w)-\n(\w)").unwrap(); // TODO: handle spaces
     rg.replace_all(&text, "$1$2").to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caption_texts(text: &str) -> Vec<(CaptionKind, String, String)> {
+        captions_from_text(text)
+            .into_iter()
+            .map(|c| (c.kind, c.number, c.text))
+            .collect()
+    }
+
+    #[test]
+    fn captions_from_pdf_text() {
+        // NOTE: poppler keeps line breaks of the layout and emits no empty lines
+        let text = "\
+4 Experiments
+We evaluate our approach on three benchmarks. Results are shown in
+Table 2. Our method improves over the baseline by a large margin on
+all datasets, see Figure 1: the encoder is shared.
+Encoder Decoder
+Figure 1: Overview of the proposed architecture. The encoder maps the
+input image to a sequence of latent tokens which are decoded
+autoregressively.
+The decoder is trained with teacher forcing and label smoothing. In all
+experiments we use the Adam optimizer with a learning rate of 10−4
+Table 2: Top-1 accuracy on ImageNet and CIFAR-100. Best results are
+in bold.
+Method ImageNet CIFAR-100
+Baseline 76.1 80.2
+Ours 79.4 83.0
+4.1 Ablations
+Removing the decoder hurts accuracy.";
+
+        assert_eq!(
+            caption_texts(text),
+            vec![
+                (
+                    CaptionKind::Figure,
+                    "1".to_string(),
+                    "Overview of the proposed architecture. The encoder maps the input image to \
+                        a sequence of latent tokens which are decoded autoregressively."
+                        .to_string()
+                ),
+                (
+                    CaptionKind::Table,
+                    "2".to_string(),
+                    "Top-1 accuracy on ImageNet and CIFAR-100. Best results are in bold."
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn captions_from_html_text() {
+        let html = r#"<html><body><article>
+            <h2 class="ltx_title ltx_title_section">3 Method</h2>
+            <div class="ltx_para"><p class="ltx_p">As shown in Figure 1, the model has two parts.</p></div>
+            <figure class="ltx_figure"><img src="x1.png">
+                <figcaption class="ltx_caption"><span class="ltx_tag ltx_tag_figure">Figure 1: </span>
+                    Model overview. Left: encoder with <math alttext="L"><mi>L</mi></math> layers.
+                    Right: decoder.</figcaption>
+            </figure>
+            <figure class="ltx_table">
+                <figcaption class="ltx_caption"><span class="ltx_tag ltx_tag_table">Table 1: </span>
+                    Results on GLUE</figcaption>
+            </figure>
+        </article></body></html>"#;
+        let (body, _) = body_from_html(html);
+
+        assert_eq!(
+            caption_texts(&body),
+            vec![
+                (
+                    CaptionKind::Figure,
+                    "1".to_string(),
+                    "Model overview. Left: encoder with L layers. Right: decoder.".to_string()
+                ),
+                (
+                    CaptionKind::Table,
+                    "1".to_string(),
+                    "Results on GLUE".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn html_headings_are_marked() {
+        let html = r#"<html><body><article class="ltx_document">
+            <h1 class="ltx_title ltx_title_document">Deep Nets</h1>
+            <div class="ltx_abstract"><h6 class="ltx_title ltx_title_abstract">Abstract</h6>
+                <p class="ltx_p">We train deep nets.</p></div>
+            <section class="ltx_section">
+                <h2 class="ltx_title ltx_title_section">
+                    <span class="ltx_tag ltx_tag_section">1 </span>Introduction</h2>
+                <div class="ltx_para"><p class="ltx_p">Nets are deep.</p></div>
+                <section class="ltx_subsection">
+                    <h3 class="ltx_title ltx_title_subsection">1.1 Data</h3>
+                    <div class="ltx_para"><p class="ltx_p">We use <math alttext="n=10"><mi>n</mi></math> images.</p></div>
+                </section>
+            </section>
+        </article></body></html>"#;
+
+        assert_eq!(
+            body_from_html(html),
+            (
+                "# Deep Nets\n\n## Abstract\n\nWe train deep nets.\n\n## 1 Introduction\n\n\
+                    Nets are deep.\n\n### 1.1 Data\n\nWe use n=10 images."
+                    .to_string(),
+                1
+            )
+        );
+    }
+
+    #[test]
+    fn long_caption_is_cut_at_sentence_end() {
+        let sentence = "This sentence describes one more panel of the figure in detail. ";
+        let text = format!("Figure 3: {}", sentence.repeat(20));
+        let captions = captions_from_text(&text);
+
+        assert_eq!(captions.len(), 1);
+        assert!(captions[0].text.chars().count() <= MAX_CAPTION_CHARS);
+        assert!(captions[0].text.ends_with("in detail."));
+    }
+
+    #[test]
+    fn rejected_match_does_not_hide_caption() {
+        let text = format!(
+            "Figure 2: {}\nFigure 2: Loss during training.",
+            "word ".repeat(300)
+        );
+
+        assert_eq!(
+            caption_texts(&text),
+            vec![(
+                CaptionKind::Figure,
+                "2".to_string(),
+                "Loss during training.".to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use arxiv_shared::{
+    arxiv_id::ArxivId,
+    models::{NewAuthor, NewSubject, PaperStatus},
+};
+
+use crate::scraper::Result;
+
+pub mod arxiv;
+
+pub type Url = String;
+
+/// One page of a source listing
+pub struct Listing {
+    /// Canonical urls of papers on the page
+    pub papers: Vec<Url>,
+    pub next_page: Option<Url>,
+}
+
+/// Everything known about a paper before its full text is downloaded
+pub struct PaperMetadata {
+    pub url: Url,
+    pub arxiv_id: Option<ArxivId>,
+    pub title: String,
+    pub description: String,
+    pub status: PaperStatus,
+    pub authors: Vec<NewAuthor>,
+    pub subjects: Vec<NewSubject>,
+}
+
+/// Raw full text of a paper, turned into body by extraction stage
+pub enum FullText {
+    Pdf(glib::Bytes),
+    /// HTML rendering produced by LaTeXML
+    Html(String),
+}
+
+impl FullText {
+    /// Size of downloaded full text in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            FullText::Pdf(bytes) => bytes.len(),
+            FullText::Html(html) => html.len(),
+        }
+    }
+
+    pub fn format(&self) -> &'static str {
+        match self {
+            FullText::Pdf(_) => "pdf",
+            FullText::Html(_) => "html",
+        }
+    }
+}
+
+/// Preprint server papers are scraped from
+#[async_trait::async_trait]
+pub trait PaperSource: Send + Sync {
+    /// Name recorded in `papers.source`
+    fn name(&self) -> &'static str;
+
+    /// Url of the listing page to start crawling from
+    fn first_page(&self, start_page: usize, papers_per_page: usize) -> Url;
+
+    async fn list(&self, page: &Url) -> Result<Listing>;
+
+    async fn fetch_metadata(&self, paper: &Url) -> Result<PaperMetadata>;
+
+    async fn fetch_full_text(&self, metadata: &PaperMetadata) -> Result<FullText>;
+}
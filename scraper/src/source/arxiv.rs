@@ -0,0 +1,286 @@
+use arxiv_shared::{
+    arxiv_id::ArxivId,
+    models::{NewAuthor, NewSubject, PaperStatus},
+};
+
+use std::sync::{Arc, OnceLock};
+
+use super::{FullText, Listing, PaperMetadata, PaperSource, Url};
+use crate::http::HttpClient;
+use crate::scraper::{Error, Result};
+
+/// Mirror meant for programmatic access, papers are downloaded from it
+const EXPORT_URL: &str = "https://export.arxiv.org";
+
+/// Papers listed by arxiv.org advanced search
+pub struct ArxivSource {
+    http: Arc<HttpClient>,
+}
+
+impl ArxivSource {
+    pub fn new(http: Arc<HttpClient>) -> Self {
+        Self { http }
+    }
+}
+
+#[async_trait::async_trait]
+impl PaperSource for ArxivSource {
+    fn name(&self) -> &'static str {
+        "arxiv"
+    }
+
+    fn first_page(&self, start_page: usize, papers_per_page: usize) -> Url {
+        format!(
+            "https://arxiv.org/search/advanced?\
+            advanced=&\
+            terms-0-operator=AND&\
+            terms-0-term=&\
+            terms-0-field=title&\
+            classification-computer_science=y&\
+            classification-physics_archives=all&\
+            classification-include_cross_list=include&\
+            date-filter_by=all_dates&\
+            date-year=&\
+            date-from_date=&\
+            date-to_date=&\
+            date-date_type=submitted_date&\
+            abstracts=show&\
+            size={}&\
+            order=-announced_date_first&\
+            start={}",
+            papers_per_page,
+            start_page * papers_per_page
+        )
+    }
+
+    async fn list(&self, page: &Url) -> Result<Listing> {
+        let dom = self.http.get_dom(page).await?;
+
+        let paper_link_selector = scraper::Selector::parse(".list-title > a").unwrap();
+        let papers = dom
+            .select(&paper_link_selector)
+            .filter_map(|l| {
+                let href = l.value().attr("href").unwrap();
+                match ArxivId::from_url(href) {
+                    Ok(id) => Some(id.unversioned().abs_url()),
+                    Err(e) => {
+                        log::warn!("Skipping paper: {e}");
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<Url>>();
+
+        let next_page_selector = scraper::Selector::parse("a.pagination-next").unwrap();
+        let mut next_page = None;
+        if let Some(next_page_href) = dom.select(&next_page_selector).next() {
+            let mut next_page_url = "https://arxiv.org".to_string();
+            let next_page_href = next_page_href.value().attr("href").unwrap();
+            next_page_url.push_str(next_page_href);
+
+            next_page = Some(next_page_url);
+        }
+
+        Ok(Listing { papers, next_page })
+    }
+
+    /// Fails when abs page has no title, e.g. when arXiv served an error page
+    /// with success status, so that stored metadata is never blanked
+    async fn fetch_metadata(&self, paper: &Url) -> Result<PaperMetadata> {
+        let arxiv_id = ArxivId::from_url(paper)?.unversioned();
+        let abs_url = format!("{EXPORT_URL}/abs/{arxiv_id}");
+        let dom = self.http.get_dom(&abs_url).await?;
+        let title = select_title(&dom).ok_or(Error::Metadata(abs_url))?;
+
+        Ok(PaperMetadata {
+            url: arxiv_id.abs_url(),
+            arxiv_id: Some(arxiv_id),
+            title,
+            description: select_description(&dom),
+            status: select_status(&dom),
+            authors: select_authors(&dom),
+            subjects: select_subjects(&dom),
+        })
+    }
+
+    /// Prefer HTML rendering at `arxiv.org/html/<id>` and fall back to PDF
+    /// for papers which were not rendered or which rendering failed to load
+    async fn fetch_full_text(&self, metadata: &PaperMetadata) -> Result<FullText> {
+        let arxiv_id = match &metadata.arxiv_id {
+            Some(arxiv_id) => arxiv_id.clone(),
+            None => ArxivId::from_url(&metadata.url)?,
+        };
+
+        match self.http.get_text_if_found(&arxiv_id.html_url()).await {
+            Ok(Some(html)) if html.contains("ltx_document") => return Ok(FullText::Html(html)),
+            Ok(_) => log::debug!("HTML: no rendering for {:?}, using PDF", metadata.url),
+            Err(e) => log::warn!(
+                "HTML: failed to fetch rendering for {:?}, using PDF: {e}",
+                metadata.url
+            ),
+        }
+
+        let pdf_url = format!("{EXPORT_URL}/pdf/{arxiv_id}");
+        Ok(FullText::Pdf(self.http.get_bytes(&pdf_url).await?))
+    }
+}
+
+fn select_title(dom: &scraper::Html) -> Option<String> {
+    let title_selector = scraper::Selector::parse("h1.title").unwrap();
+    dom.select(&title_selector)
+        .next()
+        .map(|el| {
+            el.text()
+                .collect::<String>()
+                .trim()
+                .trim_start_matches("Title:")
+                .trim_start()
+                .replace("  ", " ")
+                .to_string()
+        })
+        .filter(|title| !title.is_empty())
+}
+
+fn select_description(dom: &scraper::Html) -> String {
+    let description_selector = scraper::Selector::parse("blockquote.abstract").unwrap();
+    dom.select(&description_selector)
+        .next()
+        .map(|el| {
+            el.text()
+                .collect::<String>()
+                .trim()
+                .trim_start_matches("Abstract:")
+                .trim_start()
+                .replace('\n', " ")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+/// Look for withdrawal or replacement notice in comments and abstract
+fn select_status(dom: &scraper::Html) -> PaperStatus {
+    let notice_selector = scraper::Selector::parse("td.comments, blockquote.abstract").unwrap();
+    let notice = dom
+        .select(&notice_selector)
+        .map(|el| el.text().collect::<String>())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    static WITHDRAWN: OnceLock<regex::Regex> = OnceLock::new();
+    static SUPERSEDED: OnceLock<regex::Regex> = OnceLock::new();
+    let withdrawn = WITHDRAWN.get_or_init(|| {
+        regex::Regex::new(
+            r"(?i)\b(paper|article|submission|manuscript)\s+(has\s+been|is|was)\s+withdrawn\b|\bwithdrawn\s+by\s+(the\s+)?(authors?|arxiv)\b",
+        )
+        .unwrap()
+    });
+    let superseded = SUPERSEDED.get_or_init(|| {
+        regex::Regex::new(r"(?i)\b(superseded|replaced)\s+by\s+(arxiv:|https?://arxiv\.org/abs/)")
+            .unwrap()
+    });
+
+    if withdrawn.is_match(&notice) {
+        PaperStatus::Withdrawn
+    } else if superseded.is_match(&notice) {
+        PaperStatus::Superseded
+    } else {
+        PaperStatus::Active
+    }
+}
+
+fn select_authors(dom: &scraper::Html) -> Vec<NewAuthor> {
+    let authors_selector = scraper::Selector::parse(".authors > a").unwrap();
+    dom.select(&authors_selector)
+        .map(|a| NewAuthor {
+            name: a.text().collect::<String>(),
+        })
+        .collect()
+}
+
+fn select_subjects(dom: &scraper::Html) -> Vec<NewSubject> {
+    let subjects_selector = scraper::Selector::parse("td.subjects").unwrap();
+    dom.select(&subjects_selector)
+        .next()
+        .map(|s| {
+            s.text()
+                .collect::<String>()
+                .split(';')
+                .map(|x| NewSubject {
+                    name: x.trim().to_string(),
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(comments: &str, abstract_: &str) -> PaperStatus {
+        let html = format!(
+            r#"<html><body>
+                <h1 class="title mathjax"><span class="descriptor">Title:</span>Some paper</h1>
+                <blockquote class="abstract mathjax">
+                    <span class="descriptor">Abstract:</span>{abstract_}
+                </blockquote>
+                <table><tr><td class="tablecell comments mathjax">{comments}</td></tr></table>
+            </body></html>"#
+        );
+        select_status(&scraper::Html::parse_document(&html))
+    }
+
+    #[test]
+    fn detects_withdrawn() {
+        for comments in [
+            "This paper has been withdrawn by the author due to a crucial error in equation 5",
+            "This submission has been withdrawn by arXiv administrators due to inappropriate \
+                text overlap with external sources",
+            "The paper is withdrawn due to an error in the proof of Lemma 2",
+            "Withdrawn by the authors",
+            "This manuscript was withdrawn",
+        ] {
+            assert_eq!(status(comments, "We study graphs."), PaperStatus::Withdrawn);
+        }
+
+        assert_eq!(
+            status("", "This article has been withdrawn."),
+            PaperStatus::Withdrawn
+        );
+    }
+
+    #[test]
+    fn detects_superseded() {
+        for comments in [
+            "This paper has been superseded by arXiv:2101.00001",
+            "Replaced by https://arxiv.org/abs/2101.00001, which contains the full proof",
+        ] {
+            assert_eq!(
+                status(comments, "We study graphs."),
+                PaperStatus::Superseded
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_other_mentions() {
+        for (comments, abstract_) in [
+            ("12 pages, 3 figures", "We study graphs."),
+            (
+                "",
+                "Rofecoxib was withdrawn from the market in 2004 due to cardiovascular risks.",
+            ),
+            ("", "Drugs withdrawn by regulators are analysed."),
+            (
+                "",
+                "The softmax layer is replaced by a sparse attention mechanism.",
+            ),
+            (
+                "Accepted to NeurIPS 2023",
+                "Heat is withdrawn from the system.",
+            ),
+        ] {
+            assert_eq!(status(comments, abstract_), PaperStatus::Active);
+        }
+    }
+}
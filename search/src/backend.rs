@@ -0,0 +1,32 @@
+use arxiv_shared::{
+    db::DBConnection,
+    models::{Id, SearchHit},
+};
+
+use crate::config::{SearchBackendKind, CONFIG};
+
+/// Anything able to answer search queries with papers from the database
+#[async_trait::async_trait]
+pub trait SearchBackend: Send + Sync {
+    /// Papers matching `query`, best matches first
+    async fn search(&self, query: String, limit: usize) -> anyhow::Result<Vec<SearchHit>>;
+
+    /// Number of papers which can be found
+    async fn size(&self) -> anyhow::Result<u64>;
+
+    /// Stop finding paper which was deleted from database
+    async fn remove(&self, paper_id: Id) -> anyhow::Result<()>;
+}
+
+/// Create backend chosen by `search_backend` in config
+pub async fn create_backend(db: &DBConnection) -> anyhow::Result<Box<dyn SearchBackend>> {
+    match CONFIG.search_backend {
+        #[cfg(feature = "tantivy")]
+        SearchBackendKind::Tantivy => Ok(Box::new(crate::engine::SearchEngine::new(db).await?)),
+        #[cfg(not(feature = "tantivy"))]
+        SearchBackendKind::Tantivy => {
+            anyhow::bail!("built without `tantivy` feature, set `search_backend` to \"postgres\"")
+        }
+        SearchBackendKind::Postgres => Ok(Box::new(crate::fts::PostgresSearch::new(db.clone()))),
+    }
+}
@@ -1,11 +1,15 @@
+use arxiv_shared::models::{Id, PaperStatus};
 use console::style;
 use dialoguer::{theme::ColorfulTheme, BasicHistory, Input};
 use indicatif::{ProgressBar, ProgressStyle};
 
 use crate::{
-    config::{get_cache_dir, CONFIG, SYMSPELL, SYNONYMS},
+    backend::create_backend,
+    config::{connect_database, get_cache_dir, CONFIG},
     Flags,
 };
+#[cfg(feature = "tantivy")]
+use crate::config::{SearchBackendKind, SYMSPELL, SYNONYMS};
 
 pub async fn run_cli(flags: Flags) -> anyhow::Result<()> {
     if flags.prune {
@@ -13,9 +17,8 @@ pub async fn run_cli(flags: Flags) -> anyhow::Result<()> {
         println!("{} Pruned index", style("✔").green());
     }
 
-    let db = std::sync::Arc::new(tokio::sync::Mutex::new(
-        arxiv_shared::db::DBConnection::new(&CONFIG.database_url).await?,
-    ));
+    let db = connect_database().await?;
+    db.migrate().await?;
 
     let pb = ProgressBar::new_spinner();
     pb.enable_steady_tick(std::time::Duration::from_millis(100));
@@ -25,20 +28,23 @@ pub async fn run_cli(flags: Flags) -> anyhow::Result<()> {
             .tick_chars("◜◠◝◞◡◟✔"),
     );
     pb.set_message("Building index...");
-    let search = crate::engine::SearchEngine::new(&db).await?;
+    let search = create_backend(&db).await?;
     pb.finish_with_message("Index has been built");
 
-    let pb = ProgressBar::new_spinner();
-    pb.enable_steady_tick(std::time::Duration::from_millis(100));
-    pb.set_style(
-        ProgressStyle::with_template("{spinner:.green} {msg}")
-            .unwrap()
-            .tick_chars("◜◠◝◞◡◟✔"),
-    );
-    pb.set_message("Initializing dictionary...");
-    lazy_static::initialize(&SYMSPELL);
-    lazy_static::initialize(&SYNONYMS);
-    pb.finish_with_message("Dictianary has been loaded\n");
+    #[cfg(feature = "tantivy")]
+    if CONFIG.search_backend == SearchBackendKind::Tantivy {
+        let pb = ProgressBar::new_spinner();
+        pb.enable_steady_tick(std::time::Duration::from_millis(100));
+        pb.set_style(
+            ProgressStyle::with_template("{spinner:.green} {msg}")
+                .unwrap()
+                .tick_chars("◜◠◝◞◡◟✔"),
+        );
+        pb.set_message("Initializing dictionary...");
+        lazy_static::initialize(&SYMSPELL);
+        lazy_static::initialize(&SYNONYMS);
+        pb.finish_with_message("Dictianary has been loaded\n");
+    }
 
     let mut history = BasicHistory::new().max_entries(50).no_duplicates(true);
     loop {
@@ -48,19 +54,28 @@ pub async fn run_cli(flags: Flags) -> anyhow::Result<()> {
             .interact_text()?;
 
         let start = std::time::Instant::now();
-        let results = search.query(query, CONFIG.max_results).await?;
+        let results = search.search(query, CONFIG.max_results).await?;
         let duration = start.elapsed();
 
-        for (idx, &(_score, doc_address)) in results.iter().enumerate() {
-            let doc_id = search.get_doc_id(doc_address).unwrap();
-            let mut db = db.lock().await;
-            let paper = db.get_paper(doc_id as i32).await.unwrap();
+        let paper_ids = results.iter().map(|hit| hit.paper_id).collect::<Vec<_>>();
+        let mut found = db.get_papers_full(&paper_ids).await?;
+
+        for (idx, paper_id) in paper_ids.into_iter().enumerate() {
+            let Some(full) = found.remove(&paper_id) else {
+                continue;
+            };
+            let status = match full.paper.status {
+                PaperStatus::Active => String::new(),
+                status => format!(" {}", style(format!("[{status}]")).red()),
+            };
             println!(
-                "{:2}. {} ({})",
+                "{:2}. {}{} ({})",
                 idx + 1,
-                paper.title,
-                style(paper.url).underlined().blue()
+                full.paper.title,
+                status,
+                style(&full.paper.url).underlined().blue()
             );
+            println!("    {}", style(full.byline()).dim());
         }
 
         if results.is_empty() {
@@ -73,3 +88,42 @@ pub async fn run_cli(flags: Flags) -> anyhow::Result<()> {
         );
     }
 }
+
+pub async fn run_takedown(paper_id: Id, reason: &str) -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    db.migrate().await?;
+
+    let takedown = db.take_down_paper(paper_id, reason).await?;
+
+    // NOTE: remove paper from index even if it is already gone from database
+    #[cfg(feature = "tantivy")]
+    crate::engine::remove_from_index(&[paper_id])?;
+
+    match takedown {
+        Some(takedown) => {
+            println!(
+                "{} Took down paper {} ({})",
+                style("✔").green(),
+                paper_id,
+                style(takedown.url).underlined().blue()
+            );
+            Ok(())
+        }
+        None => anyhow::bail!("there is no paper with id {paper_id}"),
+    }
+}
+
+#[cfg(feature = "tantivy")]
+pub async fn run_index() -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    db.migrate().await?;
+
+    let applied = crate::engine::update_index(&db).await?;
+    println!("{} Applied {applied} changes to index", style("✔").green());
+    Ok(())
+}
+
+#[cfg(not(feature = "tantivy"))]
+pub async fn run_index() -> anyhow::Result<()> {
+    anyhow::bail!("built without `tantivy` feature, there is no index to update")
+}
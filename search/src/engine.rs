@@ -1,52 +1,56 @@
-use arxiv_shared::db::DBConnection;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use arxiv_shared::{
+    db::DBConnection,
+    models::{byline, ChangeEvent, Id, PaperEmbedding, PaperStatus, PaperWithAuthors, SearchHit},
+};
+use chrono::{DateTime, Utc};
 use nalgebra::{DVector, RealField};
-use tantivy::collector::TopDocs;
+use rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsModel;
+use sha2::{Digest, Sha256};
+use tantivy::collector::{DocSetCollector, TopDocs};
 use tantivy::directory::MmapDirectory;
-use tantivy::query::QueryParser;
+use tantivy::query::{AllQuery, BooleanQuery, Occur, Query, QueryParser, TermQuery};
 use tantivy::schema::IndexRecordOption;
 use tantivy::schema::*;
 use tantivy::store::Compressor;
 use tantivy::tokenizer::StopWordFilter;
-use tantivy::{doc, DocAddress, Index, Score, Searcher};
+use tantivy::{doc, DocAddress, Index, IndexReader, IndexWriter, Searcher, Term};
 
-use std::sync::Arc;
-use tokio::sync::Mutex;
-
-use crate::config::{CONFIG, MODEL, SYMSPELL, SYNONYMS};
+use crate::backend::SearchBackend;
+use crate::config::{get_cache_dir, CONFIG, MODEL, MODEL_NAME, SYMSPELL, SYNONYMS};
 
 const TOKENIZER_MAIN: &str = "searxiv-main";
 
+/// Changes may be committed out of order, so a change with lower sequence
+/// number than the last indexed one can show up later. Syncing applies again
+/// changes of transactions started this many seconds before the newest indexed
+/// change, transactions running longer may be missed
+const COMMIT_WINDOW_SECS: i64 = 600;
+
 pub struct SearchEngine {
     schema: tantivy::schema::Schema,
-    searcher: Searcher,
+    reader: IndexReader,
     index: Index,
     query_parser: QueryParser,
 }
 
 impl SearchEngine {
-    pub async fn new(db: &Arc<Mutex<DBConnection>>) -> anyhow::Result<Self> {
-        let options = TextOptions::default().set_indexing_options(
-            TextFieldIndexing::default()
-                .set_tokenizer(TOKENIZER_MAIN)
-                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
-        );
-
-        let mut schema_builder = Schema::builder();
-        let _id = schema_builder.add_u64_field("id", STORED);
-        let _url = schema_builder.add_text_field("url", STORED);
-        let _embediding = schema_builder.add_bytes_field("embedding", STORED);
-        let title = schema_builder.add_text_field("title", options.clone().set_stored());
-        let authors = schema_builder.add_text_field("authors", options.clone().set_stored());
-        let description = schema_builder.add_text_field("description", options.clone());
-        let body = schema_builder.add_text_field("body", options.clone());
-        let schema = schema_builder.build();
+    pub async fn new(db: &DBConnection) -> anyhow::Result<Self> {
+        let schema = build_schema();
+        let title = schema.get_field("title")?;
+        let authors = schema.get_field("authors")?;
+        let description = schema.get_field("description")?;
+        let body = schema.get_field("body")?;
+        let captions = schema.get_field("captions")?;
 
-        let index = create_index(&schema, db).await?;
+        let index = open_index(&schema)?;
+        sync_index(&index, db).await?;
 
         let reader = index.reader()?;
-        let searcher = reader.searcher();
         let mut query_parser =
-            QueryParser::for_index(&index, vec![title, authors, description, body]);
+            QueryParser::for_index(&index, vec![title, authors, description, body, captions]);
 
         query_parser.set_field_fuzzy(title, true, 1, true);
         query_parser.set_field_fuzzy(description, true, 1, true);
@@ -55,20 +59,17 @@ impl SearchEngine {
         query_parser.set_field_boost(authors, 1.0);
         query_parser.set_field_boost(description, 1.0);
         query_parser.set_field_boost(body, 0.1);
+        query_parser.set_field_boost(captions, 2.0);
 
         Ok(Self {
             schema,
-            searcher,
+            reader,
             index,
             query_parser,
         })
     }
 
-    pub async fn query(
-        &self,
-        query: String,
-        _limit: usize,
-    ) -> anyhow::Result<Vec<(Score, DocAddress)>> {
+    pub async fn query(&self, query: String, limit: usize) -> anyhow::Result<Vec<SearchHit>> {
         // NOTE: we get query in double quotes if it contains more than 1 word
         let query = query.trim_matches('"').to_string();
 
@@ -76,18 +77,45 @@ impl SearchEngine {
         let query = add_synonyms(query, 2);
         log::info!("Executing query {query:?}");
 
-        let search_query = self.query_parser.parse_query(&query)?;
-        let search_results = self
-            .searcher
-            .search(&search_query, &TopDocs::with_limit(100))?;
+        let mut search_query = self.query_parser.parse_query(&query)?;
+        if !CONFIG.include_withdrawn {
+            search_query = self.exclude_withdrawn(search_query)?;
+        }
+        let searcher = self.reader.searcher();
+        let search_results = searcher.search(&search_query, &TopDocs::with_limit(limit))?;
 
-        self.bert_filter(query, search_results).await
+        Ok(self
+            .bert_filter(&searcher, query, search_results, limit)
+            .await?
+            .into_iter()
+            .filter_map(|(score, doc_address)| {
+                Some(SearchHit {
+                    paper_id: self.get_doc_id(&searcher, doc_address)? as Id,
+                    score,
+                })
+            })
+            .collect())
+    }
+
+    /// Documents matching `query` except ones of withdrawn papers
+    fn exclude_withdrawn(&self, query: Box<dyn Query>) -> anyhow::Result<Box<dyn Query>> {
+        let status = self.schema.get_field("status")?;
+        let withdrawn = TermQuery::new(
+            Term::from_field_text(status, PaperStatus::Withdrawn.as_str()),
+            IndexRecordOption::Basic,
+        );
+        Ok(Box::new(BooleanQuery::new(vec![
+            (Occur::Must, query),
+            (Occur::MustNot, Box::new(withdrawn)),
+        ])))
     }
 
     pub async fn bert_filter(
         &self,
+        searcher: &Searcher,
         query: String,
         top: Vec<(f32, DocAddress)>,
+        limit: usize,
     ) -> anyhow::Result<Vec<(f32, DocAddress)>> {
         let model = &MODEL.lock().await;
 
@@ -97,7 +125,7 @@ impl SearchEngine {
         let mut new_top = top
             .into_iter()
             .map(|(score, doc_id)| -> anyhow::Result<(f32, DocAddress)> {
-                let doc = self.searcher.doc(doc_id)?;
+                let doc = searcher.doc(doc_id)?;
                 let embdeding_bytes = doc
                     .get_first(self.schema.get_field("embedding")?)
                     .unwrap()
@@ -114,13 +142,13 @@ impl SearchEngine {
 
         new_top.sort_by(|(score1, _), (score2, _)| score2.partial_cmp(score1).unwrap());
 
-        let first_n = new_top.into_iter().take(CONFIG.max_results).collect();
+        let first_n = new_top.into_iter().take(limit).collect();
 
         Ok(first_n)
     }
 
-    pub fn get_doc_id(&self, doc_address: DocAddress) -> Option<u64> {
-        let retrieved_doc = self.searcher.doc(doc_address).ok()?;
+    pub fn get_doc_id(&self, searcher: &Searcher, doc_address: DocAddress) -> Option<u64> {
+        let retrieved_doc = searcher.doc(doc_address).ok()?;
         let id_field = self.schema.get_field("id").ok()?;
         retrieved_doc.get_first(id_field)?.as_u64()
     }
@@ -133,10 +161,115 @@ impl SearchEngine {
     }
 }
 
-async fn create_index(schema: &Schema, db: &Arc<Mutex<DBConnection>>) -> anyhow::Result<Index> {
-    let index_dir = crate::config::get_cache_dir().join("index");
-    let index_already_exists = index_dir.exists();
-    let index = if index_already_exists {
+#[async_trait::async_trait]
+impl SearchBackend for SearchEngine {
+    async fn search(&self, query: String, limit: usize) -> anyhow::Result<Vec<SearchHit>> {
+        self.query(query, limit).await
+    }
+
+    async fn size(&self) -> anyhow::Result<u64> {
+        Ok(self.get_index_size()?.into())
+    }
+
+    async fn remove(&self, paper_id: Id) -> anyhow::Result<()> {
+        delete_papers(&self.index, &[paper_id])?;
+        self.reader.reload()?;
+        Ok(())
+    }
+}
+
+/// Add papers inserted or updated since the last sync to index on disk,
+/// creating it if there is none. Returns number of applied changes
+pub async fn update_index(db: &DBConnection) -> anyhow::Result<usize> {
+    sync_index(&open_index(&build_schema())?, db).await
+}
+
+/// Index papers which are missing from index on disk, e.g. ones found by
+/// fsck. Does nothing if there is no index yet
+pub async fn add_to_index(db: &DBConnection, paper_ids: &[Id]) -> anyhow::Result<()> {
+    let Some(index) = open_existing_index()? else {
+        return Ok(());
+    };
+    let id = index.schema().get_field("id")?;
+    let mut index_writer = index.writer(CONFIG.index_writer_memory_budget)?;
+    for &paper_id in paper_ids {
+        index_writer.delete_term(Term::from_field_u64(id, paper_id as u64));
+    }
+    add_papers(&index_writer, db, paper_ids).await?;
+    commit(&mut index_writer, indexed_checkpoint(&index)?.as_ref())
+}
+
+/// Delete papers from index on disk if there is one. Running searchers pick
+/// the deletion up on their own
+pub fn remove_from_index(paper_ids: &[Id]) -> anyhow::Result<()> {
+    if let Some(index) = open_existing_index()? {
+        delete_papers(&index, paper_ids)?;
+    }
+    Ok(())
+}
+
+/// Ids of papers in index on disk, `None` if there is no index yet
+pub fn indexed_paper_ids() -> anyhow::Result<Option<HashSet<Id>>> {
+    let Some(index) = open_existing_index()? else {
+        return Ok(None);
+    };
+    let id = index.schema().get_field("id")?;
+    let searcher = index.reader()?.searcher();
+
+    let mut paper_ids = HashSet::new();
+    for doc_address in searcher.search(&AllQuery, &DocSetCollector)? {
+        let doc = searcher.doc(doc_address)?;
+        if let Some(paper_id) = doc.get_first(id).and_then(|v| v.as_u64()) {
+            paper_ids.insert(paper_id as Id);
+        }
+    }
+
+    Ok(Some(paper_ids))
+}
+
+fn delete_papers(index: &Index, paper_ids: &[Id]) -> anyhow::Result<()> {
+    tracing::info!("Deleting papers {paper_ids:?} from index");
+    let id = index.schema().get_field("id")?;
+    let mut index_writer = index.writer(CONFIG.index_writer_memory_budget)?;
+    for &paper_id in paper_ids {
+        index_writer.delete_term(Term::from_field_u64(id, paper_id as u64));
+    }
+    commit(&mut index_writer, indexed_checkpoint(index)?.as_ref())
+}
+
+fn build_schema() -> Schema {
+    let options = TextOptions::default().set_indexing_options(
+        TextFieldIndexing::default()
+            .set_tokenizer(TOKENIZER_MAIN)
+            .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+    );
+
+    let mut schema_builder = Schema::builder();
+    schema_builder.add_u64_field("id", STORED | INDEXED);
+    schema_builder.add_text_field("url", STORED);
+    schema_builder.add_text_field("status", STRING);
+    schema_builder.add_bytes_field("embedding", STORED);
+    schema_builder.add_text_field("title", options.clone().set_stored());
+    schema_builder.add_text_field("authors", options.clone().set_stored());
+    schema_builder.add_text_field("description", options.clone());
+    schema_builder.add_text_field("body", options.clone());
+    schema_builder.add_text_field("captions", options);
+    schema_builder.build()
+}
+
+fn index_dir() -> PathBuf {
+    get_cache_dir().join("index")
+}
+
+/// Open index on disk, creating an empty one if there is none or it has
+/// outdated schema
+fn open_index(schema: &Schema) -> anyhow::Result<Index> {
+    let index_dir = index_dir();
+    if index_dir.exists() && !has_schema(&Index::open_in_dir(&index_dir)?, schema) {
+        tracing::info!("Index in {index_dir:?} has outdated schema: removing it");
+        std::fs::remove_dir_all(&index_dir)?;
+    }
+    let index = if index_dir.exists() {
         tracing::info!("Index dir {index_dir:?} alreay exist: opening existing index");
         Index::open_in_dir(index_dir)?
     } else {
@@ -160,41 +293,254 @@ async fn create_index(schema: &Schema, db: &Arc<Mutex<DBConnection>>) -> anyhow:
         .tokenizers()
         .register(TOKENIZER_MAIN, create_tokenizer());
 
-    if !index_already_exists {
-        let mut index_writer = index.writer(CONFIG.index_writer_memory_budget)?;
-        let model = &MODEL.lock().await;
-        let mut db = db.lock().await;
-        let papers = db.get_all_papers().await?;
-
-        for paper in papers {
-            let authors = db
-                .get_paper_authors(paper.id)
-                .await?
-                .into_iter()
-                .map(|a| a.name)
-                .collect::<Vec<_>>()
-                .join(" ");
-
-            let title_and_abstract = format!("{}. {}", paper.title, paper.description);
-            let sentences = title_and_abstract.split(". ").collect::<Vec<_>>();
-            let output = model.encode(&sentences)?;
-            let embedding = output.first().unwrap().to_owned();
-            let embedding_bytes = bincode::serialize(&embedding).unwrap();
-
-            index_writer.add_document(doc!(
-                schema.get_field("id")? => paper.id as u64,
-                schema.get_field("url")? => paper.url,
-                schema.get_field("embedding")? => embedding_bytes,
-                schema.get_field("title")? => paper.title,
-                schema.get_field("authors")? => authors,
-                schema.get_field("description")? => paper.description,
-                schema.get_field("body")? => paper.body,
-            ))?;
+    Ok(index)
+}
+
+/// Index on disk as it is, `None` if there is none
+fn open_existing_index() -> anyhow::Result<Option<Index>> {
+    let index_dir = index_dir();
+    if !index_dir.exists() {
+        return Ok(None);
+    }
+    let index = Index::open_in_dir(index_dir)?;
+    index
+        .tokenizers()
+        .register(TOKENIZER_MAIN, create_tokenizer());
+    Ok(Some(index))
+}
+
+/// Bring index up to date with database: papers inserted or updated since the
+/// last sync replace their old documents and papers taken down since are
+/// deleted. Empty index gets every paper. Returns number of applied changes
+async fn sync_index(index: &Index, db: &DBConnection) -> anyhow::Result<usize> {
+    let mut checkpoint = indexed_checkpoint(index)?;
+    let mut seq = checkpoint.as_ref().map_or(0, |c| c.change_seq);
+    if db.changes_since(seq, 1).await?.is_empty() {
+        tracing::info!("Index is up to date");
+        return Ok(0);
+    }
+
+    // NOTE: the last sync may have gone past changes which were committed
+    // later, they are applied again along with the first page
+    let mut changes = match &checkpoint {
+        Some(checkpoint) => db.recent_changes(checkpoint.recent_since(), seq).await?,
+        None => Vec::new(),
+    };
+    tracing::info!(
+        "Indexing changes after {seq} and {} recent ones",
+        changes.len()
+    );
+
+    let id = index.schema().get_field("id")?;
+    let mut index_writer = index.writer(CONFIG.index_writer_memory_budget)?;
+    let mut applied = 0;
+    loop {
+        changes.extend(db.changes_since(seq, CONFIG.index_db_page_size).await?);
+        if changes.is_empty() {
+            break;
         }
-        index_writer.commit()?;
+
+        for change in &changes {
+            index_writer.delete_term(Term::from_field_u64(id, change.paper_id as u64));
+        }
+        add_papers(&index_writer, db, &papers_to_add(&changes)).await?;
+
+        // NOTE: commit every page, so interrupted sync resumes where it stopped
+        checkpoint = Checkpoint::advance(checkpoint, &changes);
+        seq = checkpoint.as_ref().map_or(seq, |c| c.change_seq);
+        commit(&mut index_writer, checkpoint.as_ref())?;
+        applied += changes.len();
+        changes.clear();
     }
+    tracing::info!("Applied {applied} changes to index");
 
-    Ok(index)
+    Ok(applied)
+}
+
+/// Papers which exist after `changes` ordered by change, each paper once
+fn papers_to_add(changes: &[ChangeEvent]) -> Vec<Id> {
+    let mut latest = HashMap::new();
+    for (i, change) in changes.iter().enumerate() {
+        latest.insert(change.paper_id, (i, change.deleted));
+    }
+    let mut paper_ids = latest
+        .into_iter()
+        .filter(|(_, (_, deleted))| !deleted)
+        .map(|(paper_id, (i, _))| (i, paper_id))
+        .collect::<Vec<_>>();
+    paper_ids.sort();
+    paper_ids
+        .into_iter()
+        .map(|(_, paper_id)| paper_id)
+        .collect()
+}
+
+/// Position of index in the change feed, kept in payload of the last commit
+#[derive(Clone, Debug, PartialEq)]
+struct Checkpoint {
+    /// Last change applied to index
+    change_seq: i64,
+    /// Newest time of applied changes
+    updated_at: DateTime<Utc>,
+}
+
+impl Checkpoint {
+    fn parse(payload: &str) -> Option<Checkpoint> {
+        let (change_seq, updated_at) = payload.split_once(' ')?;
+        Some(Checkpoint {
+            change_seq: change_seq.parse().ok()?,
+            updated_at: DateTime::parse_from_rfc3339(updated_at)
+                .ok()?
+                .with_timezone(&Utc),
+        })
+    }
+
+    fn to_payload(&self) -> String {
+        format!("{} {}", self.change_seq, self.updated_at.to_rfc3339())
+    }
+
+    /// Checkpoint after applying `changes`, which may include changes older
+    /// than `checkpoint` committed out of order
+    fn advance(checkpoint: Option<Checkpoint>, changes: &[ChangeEvent]) -> Option<Checkpoint> {
+        changes.iter().fold(checkpoint, |checkpoint, change| {
+            Some(match checkpoint {
+                Some(c) => Checkpoint {
+                    change_seq: c.change_seq.max(change.change_seq),
+                    updated_at: c.updated_at.max(change.updated_at),
+                },
+                None => Checkpoint {
+                    change_seq: change.change_seq,
+                    updated_at: change.updated_at,
+                },
+            })
+        })
+    }
+
+    /// Start of transactions which may have committed after the checkpoint
+    /// was taken
+    fn recent_since(&self) -> DateTime<Utc> {
+        self.updated_at - chrono::Duration::seconds(COMMIT_WINDOW_SECS)
+    }
+}
+
+/// Position of index in the change feed, `None` if index was never synced
+fn indexed_checkpoint(index: &Index) -> anyhow::Result<Option<Checkpoint>> {
+    Ok(index
+        .load_metas()?
+        .payload
+        .and_then(|payload| Checkpoint::parse(&payload)))
+}
+
+/// Commit keeping `checkpoint` in payload, tantivy drops payload of previous
+/// commit otherwise
+fn commit(index_writer: &mut IndexWriter, checkpoint: Option<&Checkpoint>) -> anyhow::Result<()> {
+    let mut prepared_commit = index_writer.prepare_commit()?;
+    if let Some(checkpoint) = checkpoint {
+        prepared_commit.set_payload(&checkpoint.to_payload());
+    }
+    prepared_commit.commit()?;
+    Ok(())
+}
+
+/// Add documents of papers with given ids, papers which no longer exist are
+/// skipped
+async fn add_papers(
+    index_writer: &IndexWriter,
+    db: &DBConnection,
+    paper_ids: &[Id],
+) -> anyhow::Result<()> {
+    if paper_ids.is_empty() {
+        return Ok(());
+    }
+
+    let schema = index_writer.index().schema();
+    let papers = db.get_papers_with_authors(paper_ids).await?;
+    let model = &MODEL.lock().await;
+    let mut embeddings = embed_papers(db, model, &papers).await?;
+
+    for PaperWithAuthors {
+        paper,
+        authors,
+        captions,
+    } in papers
+    {
+        let authors = byline(&authors);
+        let captions = captions
+            .into_iter()
+            .map(|c| format!("{} {}. {}", c.kind, c.number, c.text))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let embedding = embeddings.remove(&paper.id).unwrap_or_default();
+        let embedding_bytes = bincode::serialize(&embedding).unwrap();
+
+        index_writer.add_document(doc!(
+            schema.get_field("id")? => paper.id as u64,
+            schema.get_field("url")? => paper.url,
+            schema.get_field("status")? => paper.status.to_string(),
+            schema.get_field("embedding")? => embedding_bytes,
+            schema.get_field("title")? => paper.title,
+            schema.get_field("authors")? => authors,
+            schema.get_field("description")? => paper.description,
+            schema.get_field("body")? => paper.body,
+            schema.get_field("captions")? => captions,
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Embeddings of title and abstract of every paper, ones stored in database
+/// are reused unless the text changed since they were computed
+async fn embed_papers(
+    db: &DBConnection,
+    model: &SentenceEmbeddingsModel,
+    papers: &[PaperWithAuthors],
+) -> anyhow::Result<HashMap<Id, Vec<f32>>> {
+    let paper_ids = papers.iter().map(|p| p.paper.id).collect::<Vec<_>>();
+    let mut stored = db.get_embeddings(MODEL_NAME, &paper_ids).await?;
+
+    let mut embeddings = HashMap::new();
+    let mut computed = Vec::new();
+    for PaperWithAuthors { paper, .. } in papers {
+        let title_and_abstract = format!("{}. {}", paper.title, paper.description);
+        let content_hash = format!("{:x}", Sha256::digest(&title_and_abstract));
+
+        let embedding = match stored.remove(&paper.id) {
+            Some(stored) if stored.content_hash == content_hash => stored.embedding,
+            _ => {
+                let sentences = title_and_abstract.split(". ").collect::<Vec<_>>();
+                let output = model.encode(&sentences)?;
+                let embedding = output.first().unwrap().to_owned();
+                computed.push(PaperEmbedding {
+                    paper_id: paper.id,
+                    model: MODEL_NAME.to_string(),
+                    content_hash,
+                    embedding: embedding.clone(),
+                });
+                embedding
+            }
+        };
+        embeddings.insert(paper.id, embedding);
+    }
+
+    if !computed.is_empty() {
+        tracing::info!("Computed {} new embeddings", computed.len());
+        db.upsert_embeddings(computed).await?;
+    }
+
+    Ok(embeddings)
+}
+
+/// Whether index on disk was built with every field of the current schema
+/// indexed and stored the same way
+fn has_schema(index: &Index, schema: &Schema) -> bool {
+    let index_schema = index.schema();
+    schema.fields().all(|(_, entry)| {
+        index_schema
+            .get_field(entry.name())
+            .is_ok_and(|field| index_schema.get_field_entry(field) == entry)
+    })
 }
 
 fn cosine_similarity<T: RealField>(a: &DVector<T>, b: &DVector<T>) -> T {
@@ -257,3 +603,90 @@ fn add_synonyms(query: String, n: usize) -> String {
 
     query_words_with_synonyms.join(" ")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn change(paper_id: Id, change_seq: i64, updated_at: &str, deleted: bool) -> ChangeEvent {
+        ChangeEvent {
+            paper_id,
+            change_seq,
+            updated_at: at(updated_at),
+            deleted,
+        }
+    }
+
+    #[test]
+    fn checkpoint_round_trips_through_payload() {
+        let checkpoint = Checkpoint {
+            change_seq: 42,
+            updated_at: at("2023-12-14T10:00:00.123456Z"),
+        };
+        assert_eq!(
+            Checkpoint::parse(&checkpoint.to_payload()),
+            Some(checkpoint)
+        );
+        assert_eq!(Checkpoint::parse("42"), None);
+        assert_eq!(Checkpoint::parse(""), None);
+    }
+
+    #[test]
+    fn checkpoint_advances_past_changes() {
+        let checkpoint = Checkpoint::advance(
+            None,
+            &[
+                change(1, 10, "2023-12-14T10:05:00Z", false),
+                change(2, 11, "2023-12-14T10:01:00Z", false),
+            ],
+        );
+        assert_eq!(
+            checkpoint,
+            Some(Checkpoint {
+                change_seq: 11,
+                updated_at: at("2023-12-14T10:05:00Z"),
+            })
+        );
+
+        // NOTE: change committed late has lower sequence number, but may be
+        // the newest one
+        let checkpoint =
+            Checkpoint::advance(checkpoint, &[change(3, 9, "2023-12-14T10:06:00Z", false)]);
+        assert_eq!(
+            checkpoint,
+            Some(Checkpoint {
+                change_seq: 11,
+                updated_at: at("2023-12-14T10:06:00Z"),
+            })
+        );
+        assert_eq!(Checkpoint::advance(None, &[]), None);
+    }
+
+    #[test]
+    fn resumes_from_commit_window() {
+        let checkpoint = Checkpoint {
+            change_seq: 11,
+            updated_at: at("2023-12-14T10:15:00Z"),
+        };
+        assert_eq!(checkpoint.recent_since(), at("2023-12-14T10:05:00Z"));
+    }
+
+    #[test]
+    fn latest_change_of_paper_wins() {
+        let changes = [
+            change(1, 5, "2023-12-14T10:00:00Z", false),
+            change(2, 6, "2023-12-14T10:00:00Z", false),
+            change(3, 7, "2023-12-14T10:00:00Z", false),
+            change(1, 8, "2023-12-14T10:00:00Z", true),
+            change(2, 9, "2023-12-14T10:00:00Z", false),
+            change(4, 10, "2023-12-14T10:00:00Z", true),
+        ];
+        assert_eq!(papers_to_add(&changes), [3, 2]);
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashSet;
+
+use arxiv_shared::models::{Id, IntegrityIssue};
+use console::style;
+
+use crate::config::connect_database;
+
+/// Report problems in database and search index, with `fix` repair those
+/// which can be repaired without scraping again
+pub async fn run_fsck(fix: bool) -> anyhow::Result<()> {
+    let db = connect_database().await?;
+    db.migrate().await?;
+
+    #[cfg(feature = "tantivy")]
+    let embedding_model = Some(crate::config::MODEL_NAME);
+    #[cfg(not(feature = "tantivy"))]
+    let embedding_model = None;
+
+    let issues = db.check_integrity(embedding_model).await?;
+    for issue in &issues {
+        println!("{} {}", style("✘").red(), describe(issue));
+    }
+
+    let (not_indexed, not_in_db) = check_index(&db).await?;
+    for paper_id in &not_indexed {
+        println!(
+            "{} Paper {paper_id} is missing from index",
+            style("✘").red()
+        );
+    }
+    for paper_id in &not_in_db {
+        println!(
+            "{} Index has paper {paper_id} which is not in database",
+            style("✘").red()
+        );
+    }
+
+    let problems = issues.len() + not_indexed.len() + not_in_db.len();
+    if problems == 0 {
+        println!("{} No problems found", style("✔").green());
+        return Ok(());
+    }
+    if !fix {
+        anyhow::bail!("found {problems} problems, run with --fix to repair them");
+    }
+
+    let mut deleted = Vec::new();
+    let mut empty = HashSet::new();
+    let mut stale_embeddings = false;
+    let mut orphans = false;
+    for issue in issues {
+        match issue {
+            IntegrityIssue::DuplicatePaper {
+                paper_id,
+                original_id,
+                ..
+            } => {
+                let reason = format!("duplicate of paper {original_id}");
+                if db.take_down_paper(paper_id, &reason).await?.is_some() {
+                    deleted.push(paper_id);
+                }
+            }
+            IntegrityIssue::EmptyTitle { paper_id, .. }
+            | IntegrityIssue::EmptyBody { paper_id, .. } => {
+                empty.insert(paper_id);
+            }
+            IntegrityIssue::StaleEmbedding { .. } => stale_embeddings = true,
+            IntegrityIssue::OrphanAuthor { .. } | IntegrityIssue::OrphanSubject { .. } => {
+                orphans = true
+            }
+        }
+    }
+    if !deleted.is_empty() {
+        println!(
+            "{} Took down {} duplicates",
+            style("✔").green(),
+            deleted.len()
+        );
+    }
+
+    // NOTE: empty papers are deleted without a tombstone so they are scraped
+    // again, other indexes learn about the deletion from the change feed
+    let empty = empty
+        .into_iter()
+        .filter(|paper_id| !deleted.contains(paper_id))
+        .collect::<Vec<_>>();
+    if !empty.is_empty() {
+        let count = db.delete_papers(&empty).await?;
+        println!("{} Deleted {count} empty papers", style("✔").green());
+        deleted.extend(empty);
+    }
+
+    if let (true, Some(model)) = (stale_embeddings, embedding_model) {
+        let count = db.delete_stale_embeddings(model).await?;
+        println!("{} Deleted {count} stale embeddings", style("✔").green());
+    }
+
+    // NOTE: deleting papers may leave new orphans behind
+    if orphans || !deleted.is_empty() {
+        let count = db.delete_orphans().await?;
+        println!(
+            "{} Deleted {count} orphan authors and subjects",
+            style("✔").green()
+        );
+    }
+
+    deleted.extend(not_in_db);
+    remove_from_index(&deleted)?;
+
+    let not_indexed = not_indexed
+        .into_iter()
+        .filter(|paper_id| !deleted.contains(paper_id))
+        .collect::<Vec<_>>();
+    add_to_index(&db, &not_indexed).await?;
+
+    Ok(())
+}
+
+fn describe(issue: &IntegrityIssue) -> String {
+    match issue {
+        IntegrityIssue::OrphanAuthor { id, name } => {
+            format!("Author {id} ({name:?}) has no papers")
+        }
+        IntegrityIssue::OrphanSubject { id, name } => {
+            format!("Subject {id} ({name:?}) has no papers")
+        }
+        IntegrityIssue::EmptyTitle { paper_id, url } => {
+            format!("Paper {paper_id} ({url}) has empty title")
+        }
+        IntegrityIssue::EmptyBody { paper_id, url } => {
+            format!("Paper {paper_id} ({url}) has empty body")
+        }
+        IntegrityIssue::DuplicatePaper {
+            paper_id,
+            url,
+            original_id,
+        } => format!("Paper {paper_id} ({url}) is a duplicate of paper {original_id}"),
+        IntegrityIssue::StaleEmbedding { paper_id, model } => {
+            format!("Paper {paper_id} has embedding of unused model {model:?}")
+        }
+    }
+}
+
+/// Papers missing from index and papers in index missing from database.
+/// Nothing to check unless tantivy index is used
+#[cfg(feature = "tantivy")]
+async fn check_index(db: &arxiv_shared::db::DBConnection) -> anyhow::Result<(Vec<Id>, Vec<Id>)> {
+    use crate::config::{SearchBackendKind, CONFIG};
+    use arxiv_shared::models::PaperFilter;
+
+    if !matches!(CONFIG.search_backend, SearchBackendKind::Tantivy) {
+        return Ok(Default::default());
+    }
+    let Some(indexed) = crate::engine::indexed_paper_ids()? else {
+        return Ok(Default::default());
+    };
+
+    let stored = db
+        .select_papers(&PaperFilter::default())
+        .await?
+        .into_iter()
+        .map(|p| p.id)
+        .collect::<HashSet<_>>();
+
+    let mut not_indexed = stored.difference(&indexed).copied().collect::<Vec<_>>();
+    let mut not_in_db = indexed.difference(&stored).copied().collect::<Vec<_>>();
+    not_indexed.sort();
+    not_in_db.sort();
+
+    Ok((not_indexed, not_in_db))
+}
+
+#[cfg(not(feature = "tantivy"))]
+async fn check_index(_db: &arxiv_shared::db::DBConnection) -> anyhow::Result<(Vec<Id>, Vec<Id>)> {
+    Ok(Default::default())
+}
+
+#[cfg(feature = "tantivy")]
+async fn add_to_index(db: &arxiv_shared::db::DBConnection, paper_ids: &[Id]) -> anyhow::Result<()> {
+    if !paper_ids.is_empty() {
+        crate::engine::add_to_index(db, paper_ids).await?;
+        println!(
+            "{} Indexed {} missing papers",
+            style("✔").green(),
+            paper_ids.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "tantivy"))]
+async fn add_to_index(
+    _db: &arxiv_shared::db::DBConnection,
+    _paper_ids: &[Id],
+) -> anyhow::Result<()> {
+    Ok(())
+}
+
+#[cfg(feature = "tantivy")]
+fn remove_from_index(paper_ids: &[Id]) -> anyhow::Result<()> {
+    if !paper_ids.is_empty() {
+        crate::engine::remove_from_index(paper_ids)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "tantivy"))]
+fn remove_from_index(_paper_ids: &[Id]) -> anyhow::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use arxiv_shared::{
+    db::DBConnection,
+    models::{Id, SearchHit},
+};
+
+use crate::backend::SearchBackend;
+use crate::config::CONFIG;
+
+/// Search with Postgres full-text search over weighted title, abstract and
+/// body, needs no local index
+pub struct PostgresSearch {
+    db: DBConnection,
+}
+
+impl PostgresSearch {
+    pub fn new(db: DBConnection) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait::async_trait]
+impl SearchBackend for PostgresSearch {
+    async fn search(&self, query: String, limit: usize) -> anyhow::Result<Vec<SearchHit>> {
+        // NOTE: we get query in double quotes if it contains more than 1 word
+        let query = query.trim_matches('"');
+        log::info!("Executing query {query:?}");
+
+        Ok(self
+            .db
+            .search_papers(query, limit as i64, CONFIG.include_withdrawn)
+            .await?)
+    }
+
+    async fn size(&self) -> anyhow::Result<u64> {
+        Ok(self.db.count_papers().await? as u64)
+    }
+
+    async fn remove(&self, _paper_id: Id) -> anyhow::Result<()> {
+        // NOTE: deleted paper is gone from `papers` along with its search vector
+        Ok(())
+    }
+}
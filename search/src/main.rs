@@ -1,6 +1,10 @@
+mod backend;
 mod cli;
 mod config;
+#[cfg(feature = "tantivy")]
 mod engine;
+mod fsck;
+mod fts;
 mod server;
 
 use clap::{Parser, Subcommand};
@@ -18,6 +22,27 @@ pub enum RunMode {
     Cli(Flags),
     /// Run as web server
     Server(Flags),
+    /// Apply pending database migrations and exit
+    Migrate,
+    /// Delete paper from database and search index, leaving a tombstone so
+    /// that it is not scraped again
+    Takedown {
+        /// Id of the paper
+        id: i32,
+        /// Why the paper is taken down
+        #[arg(short, long)]
+        reason: String,
+    },
+    /// Add papers inserted or updated since the last run to search index and
+    /// delete papers taken down since
+    Index,
+    /// Check database and search index for orphans, empty and duplicate
+    /// papers, stale embeddings and papers missing from either of them
+    Fsck {
+        /// Repair found problems where possible
+        #[arg(long)]
+        fix: bool,
+    },
 }
 
 #[derive(Parser, Debug)]
@@ -33,5 +58,12 @@ async fn main() -> anyhow::Result<()> {
     match cli.mode {
         RunMode::Cli(flags) => cli::run_cli(flags).await,
         RunMode::Server(flags) => server::run_server(flags).await,
+        RunMode::Migrate => {
+            config::connect_database().await?.migrate().await?;
+            Ok(())
+        }
+        RunMode::Takedown { id, reason } => cli::run_takedown(id, &reason).await,
+        RunMode::Index => cli::run_index().await,
+        RunMode::Fsck { fix } => fsck::run_fsck(fix).await,
     }
 }
@@ -3,18 +3,20 @@ use utoipa::OpenApi;
 use utoipa_rapidoc::RapiDoc;
 
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
 use crate::{
-    config::{get_cache_dir, CONFIG, SYMSPELL, SYNONYMS},
+    backend::create_backend,
+    config::{connect_database, get_cache_dir, CONFIG},
     Flags,
 };
+#[cfg(feature = "tantivy")]
+use crate::config::{SearchBackendKind, SYMSPELL, SYNONYMS};
 
 #[derive(utoipa::OpenApi)]
 #[openapi(
-        paths(searxiv::root, searxiv::search),
+        paths(searxiv::root, searxiv::search, searxiv::get_paper, searxiv::take_down),
         components(
-            schemas(searxiv::PaperInfo)
+            schemas(searxiv::PaperInfo, searxiv::TakedownRequest)
         ),
         tags(
             (name = "searxiv", description = "Search through pages in arxiv.org")
@@ -33,16 +35,19 @@ pub async fn run_server(flags: Flags) -> anyhow::Result<()> {
         log::info!("Pruned index");
     }
 
-    let db = std::sync::Arc::new(tokio::sync::Mutex::new(
-        arxiv_shared::db::DBConnection::new(&CONFIG.database_url).await?,
-    ));
-    let engine = Mutex::new(crate::engine::SearchEngine::new(&db).await?);
+    let db = connect_database().await?;
+    db.migrate().await?;
+    let backend = create_backend(&db).await?;
 
-    let store = Arc::new(searxiv::Store { engine, db });
+    let store = Arc::new(searxiv::Store { backend, db });
     let app = axum::Router::new()
         .route("/", axum::routing::get(searxiv::root))
         .route("/index-size", axum::routing::get(searxiv::index_size))
         .route("/search", axum::routing::get(searxiv::search))
+        .route(
+            "/papers/:id",
+            axum::routing::get(searxiv::get_paper).delete(searxiv::take_down),
+        )
         .merge(RapiDoc::with_openapi("/api-docs/openapi.json", ApiDoc::openapi()).path("/docs"))
         .layer(CorsLayer::permissive())
         .with_state(store);
@@ -51,32 +56,54 @@ pub async fn run_server(flags: Flags) -> anyhow::Result<()> {
         tokio::net::TcpListener::bind(format!("0.0.0.0:{}", CONFIG.server_specific.port))
             .await?;
 
-    log::info!("Loading dictionary...");
-    lazy_static::initialize(&SYMSPELL);
-    log::info!("Loaded dictionary...");
+    #[cfg(feature = "tantivy")]
+    if CONFIG.search_backend == SearchBackendKind::Tantivy {
+        log::info!("Loading dictionary...");
+        lazy_static::initialize(&SYMSPELL);
+        log::info!("Loaded dictionary...");
 
-    log::info!("Loading synonyms...");
-    lazy_static::initialize(&SYNONYMS);
-    log::info!("Loaded synonyms...");
+        log::info!("Loading synonyms...");
+        lazy_static::initialize(&SYNONYMS);
+        log::info!("Loaded synonyms...");
+    }
 
     Ok(axum::serve(listener, app).await?)
 }
 
 mod searxiv {
     use axum::{
-        extract::{Query, State},
+        extract::{Path, Query, State},
+        http::{header, HeaderMap, StatusCode},
         Json,
     };
     use std::sync::Arc;
-    use tokio::sync::Mutex;
 
-    use arxiv_shared::db::DBConnection;
+    use arxiv_shared::{
+        db::{self, DBConnection},
+        models::{Id, PaperFull},
+    };
 
-    use crate::config::CONFIG;
+    use crate::{backend::SearchBackend, config::CONFIG};
 
     pub(super) struct Store {
-        pub(crate) engine: Mutex<crate::engine::SearchEngine>,
-        pub(crate) db: Arc<Mutex<DBConnection>>,
+        pub(crate) backend: Box<dyn SearchBackend>,
+        pub(crate) db: DBConnection,
+    }
+
+    /// 404 for missing rows, 503 when retrying later may help
+    fn db_error_status(e: &db::Error) -> StatusCode {
+        match e {
+            db::Error::NotFound => StatusCode::NOT_FOUND,
+            e if e.is_transient() => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Search backends may fail with database errors, e.g. full-text search
+    /// in Postgres
+    fn backend_error_status(e: &anyhow::Error) -> StatusCode {
+        e.downcast_ref::<db::Error>()
+            .map_or(StatusCode::INTERNAL_SERVER_ERROR, db_error_status)
     }
 
     #[utoipa::path(
@@ -98,13 +125,7 @@ mod searxiv {
         )
     )]
     pub(super) async fn index_size(State(state): State<Arc<Store>>) -> String {
-        state
-            .engine
-            .lock()
-            .await
-            .get_index_size()
-            .unwrap_or(0)
-            .to_string()
+        state.backend.size().await.unwrap_or(0).to_string()
     }
 
     /// Paper info
@@ -118,6 +139,20 @@ mod searxiv {
         description: String,
         /// Url to the paper on arxiv.org
         url: String,
+        /// Status of the paper: active, withdrawn or superseded
+        status: String,
+    }
+
+    impl From<PaperFull> for PaperInfo {
+        fn from(full: PaperFull) -> Self {
+            PaperInfo {
+                authors: full.byline(),
+                title: full.paper.title,
+                description: full.paper.description,
+                url: full.paper.url,
+                status: full.paper.status.to_string(),
+            }
+        }
     }
 
     #[derive(serde::Deserialize, utoipa::IntoParams)]
@@ -132,42 +167,124 @@ mod searxiv {
             SearchQuery
         ),
         responses(
-            (status = 200, description = "Search for papers", body = [PaperInfo])
+            (status = 200, description = "Search for papers", body = [PaperInfo]),
+            (status = 503, description = "Database is unavailable, try again later")
         )
     )]
     pub(super) async fn search(
         State(state): State<Arc<Store>>,
         query: Query<SearchQuery>,
-    ) -> Json<Vec<PaperInfo>> {
+    ) -> Result<Json<Vec<PaperInfo>>, StatusCode> {
         let results = state
-            .engine
-            .lock()
-            .await
-            .query(query.query.clone(), CONFIG.max_results)
+            .backend
+            .search(query.query.clone(), CONFIG.max_results)
             .await
-            .unwrap();
+            .map_err(|e| {
+                log::error!("Failed to search for {:?}: {e:?}", query.query);
+                backend_error_status(&e)
+            })?;
+
+        let paper_ids = results.iter().map(|hit| hit.paper_id).collect::<Vec<_>>();
+        let mut found = state.db.get_papers_full(&paper_ids).await.map_err(|e| {
+            log::error!("Failed to get papers {paper_ids:?}: {e:?}");
+            db_error_status(&e)
+        })?;
 
         let mut papers = Vec::new();
 
-        for (_score, doc_address) in results {
-            let doc_id = state.engine.lock().await.get_doc_id(doc_address).unwrap();
-            let mut db = state.db.lock().await;
-            let paper = db.get_paper(doc_id as i32).await.unwrap();
-            let authors = db
-                .get_paper_authors(paper.id)
-                .await
-                .unwrap()
-                .iter()
-                .map(|a| a.name.to_string())
-                .collect::<Vec<_>>()
-                .join(", ");
-            papers.push(PaperInfo {
-                title: paper.title,
-                authors,
-                description: paper.description,
-                url: paper.url,
-            })
+        for paper_id in paper_ids {
+            let Some(full) = found.remove(&paper_id) else {
+                continue;
+            };
+            papers.push(PaperInfo::from(full))
+        }
+        Ok(Json(papers))
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/papers/{id}",
+        params(
+            ("id" = i32, Path, description = "Id of the paper")
+        ),
+        responses(
+            (status = 200, description = "Get paper by id", body = PaperInfo),
+            (status = 404, description = "There is no such paper"),
+            (status = 503, description = "Database is unavailable, try again later")
+        )
+    )]
+    pub(super) async fn get_paper(
+        State(state): State<Arc<Store>>,
+        Path(paper_id): Path<Id>,
+    ) -> Result<Json<PaperInfo>, StatusCode> {
+        match state.db.get_paper_full(paper_id).await {
+            Ok(Some(full)) => Ok(Json(PaperInfo::from(full))),
+            Ok(None) => Err(StatusCode::NOT_FOUND),
+            Err(e) => {
+                log::error!("Failed to get paper {paper_id}: {e:?}");
+                Err(db_error_status(&e))
+            }
+        }
+    }
+
+    /// Takedown details
+    #[derive(serde::Deserialize, utoipa::ToSchema)]
+    pub(super) struct TakedownRequest {
+        /// Why the paper is taken down
+        reason: String,
+    }
+
+    #[utoipa::path(
+        delete,
+        path = "/papers/{id}",
+        params(
+            ("id" = i32, Path, description = "Id of the paper")
+        ),
+        request_body = TakedownRequest,
+        responses(
+            (status = 204, description = "Paper is deleted from database and index"),
+            (status = 401, description = "Missing or wrong admin token in `Authorization: Bearer` header"),
+            (status = 403, description = "Admin endpoints are disabled"),
+            (status = 404, description = "There is no such paper"),
+            (status = 503, description = "Database is unavailable, try again later")
+        )
+    )]
+    pub(super) async fn take_down(
+        State(state): State<Arc<Store>>,
+        Path(paper_id): Path<Id>,
+        headers: HeaderMap,
+        Json(request): Json<TakedownRequest>,
+    ) -> StatusCode {
+        let Some(admin_token) = &CONFIG.server_specific.admin_token else {
+            return StatusCode::FORBIDDEN;
+        };
+        let authorized = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| token == admin_token);
+        if !authorized {
+            return StatusCode::UNAUTHORIZED;
+        }
+
+        let takedown = match state.db.take_down_paper(paper_id, &request.reason).await {
+            Ok(takedown) => takedown,
+            Err(e) => {
+                log::error!("Failed to take down paper {paper_id}: {e:?}");
+                return db_error_status(&e);
+            }
+        };
+        if let Err(e) = state.backend.remove(paper_id).await {
+            log::error!("Failed to remove paper {paper_id} from index: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+
+        match takedown {
+            Some(takedown) => {
+                log::info!("Took down paper {paper_id} ({})", takedown.url);
+                StatusCode::NO_CONTENT
+            }
+            None => StatusCode::NOT_FOUND,
         }
-        Json(papers)
     }
 }
@@ -1,7 +1,8 @@
-CREATE DATABASE arxiv;
-\c arxiv;
+-- Schema of databases created from the former `db/init.sql`. Every migration
+-- is written so it can run against such databases, which already contain
+-- some of the objects it creates
 
-CREATE TABLE papers (
+CREATE TABLE IF NOT EXISTS papers (
   id SERIAL PRIMARY KEY,
   url VARCHAR NOT NULL UNIQUE,
   title VARCHAR NOT NULL,
@@ -9,26 +10,24 @@ CREATE TABLE papers (
   body TEXT NOT NULL
 );
 
-CREATE TABLE authors (
+CREATE TABLE IF NOT EXISTS authors (
   id SERIAL PRIMARY KEY,
   name VARCHAR NOT NULL UNIQUE
 );
 
-CREATE TABLE paper_author (
+CREATE TABLE IF NOT EXISTS paper_author (
   paper_id INTEGER REFERENCES papers (id),
   author_id INTEGER REFERENCES authors (id),
   PRIMARY KEY (paper_id, author_id)
 );
 
-
-CREATE TABLE subjects (
+CREATE TABLE IF NOT EXISTS subjects (
   id SERIAL PRIMARY KEY,
   name VARCHAR NOT NULL UNIQUE
 );
 
-CREATE TABLE paper_subject (
+CREATE TABLE IF NOT EXISTS paper_subject (
   paper_id INTEGER REFERENCES papers (id),
   subject_id INTEGER REFERENCES subjects (id),
   PRIMARY KEY (paper_id, subject_id)
 );
-
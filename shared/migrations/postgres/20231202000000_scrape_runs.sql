@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS scrape_runs (
+  id SERIAL PRIMARY KEY,
+  started_at TIMESTAMPTZ NOT NULL,
+  finished_at TIMESTAMPTZ NOT NULL,
+  query TEXT NOT NULL,
+  pages_scraped INTEGER NOT NULL,
+  papers_found INTEGER NOT NULL,
+  papers_stored INTEGER NOT NULL,
+  papers_failed INTEGER NOT NULL,
+  bytes_downloaded BIGINT NOT NULL,
+  failures JSONB NOT NULL
+);
@@ -0,0 +1,11 @@
+ALTER TABLE papers ADD COLUMN IF NOT EXISTS source VARCHAR NOT NULL DEFAULT 'arxiv';
+ALTER TABLE papers ADD COLUMN IF NOT EXISTS fetched_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE TABLE IF NOT EXISTS paper_changes (
+  id SERIAL PRIMARY KEY,
+  paper_id INTEGER NOT NULL REFERENCES papers (id),
+  changed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  field VARCHAR NOT NULL,
+  old_value TEXT,
+  new_value TEXT
+);
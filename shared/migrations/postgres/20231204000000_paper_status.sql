@@ -0,0 +1,7 @@
+DO $$ BEGIN
+  CREATE TYPE paper_status AS ENUM ('active', 'withdrawn', 'superseded');
+EXCEPTION
+  WHEN duplicate_object THEN NULL;
+END $$;
+
+ALTER TABLE papers ADD COLUMN IF NOT EXISTS status paper_status NOT NULL DEFAULT 'active';
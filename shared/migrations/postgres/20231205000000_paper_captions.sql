@@ -0,0 +1,15 @@
+DO $$ BEGIN
+  CREATE TYPE caption_kind AS ENUM ('figure', 'table');
+EXCEPTION
+  WHEN duplicate_object THEN NULL;
+END $$;
+
+CREATE TABLE IF NOT EXISTS paper_captions (
+  id SERIAL PRIMARY KEY,
+  paper_id INTEGER NOT NULL REFERENCES papers (id),
+  kind caption_kind NOT NULL,
+  number VARCHAR NOT NULL,
+  text TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS paper_captions_paper_id ON paper_captions (paper_id);
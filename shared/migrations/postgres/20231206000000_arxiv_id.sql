@@ -0,0 +1,53 @@
+-- Key arXiv papers by canonical identifier instead of url. Papers which were
+-- stored under several urls (`arxiv.org/abs`, `export.arxiv.org/abs`, ...) are
+-- merged into the oldest row and all urls are rewritten to `arxiv.org/abs/<id>`.
+
+ALTER TABLE papers ADD COLUMN IF NOT EXISTS arxiv_id VARCHAR;
+
+UPDATE papers SET arxiv_id = coalesce(m[3], m[1] || '/' || m[2])
+FROM (
+  SELECT id, regexp_match(
+    url,
+    '/(?:abs|pdf|html)/(?:([a-z][a-z-]*)(?:\.[A-Z]{2})?/([0-9]{7})|([0-9]{4}\.[0-9]{4,5}))'
+  ) AS m
+  FROM papers
+  WHERE source = 'arxiv'
+) AS parsed
+WHERE papers.id = parsed.id AND parsed.m IS NOT NULL;
+
+CREATE TEMPORARY TABLE paper_duplicates AS
+  SELECT id, keep_id FROM (
+    SELECT id, min(id) OVER (PARTITION BY arxiv_id) AS keep_id
+    FROM papers
+    WHERE arxiv_id IS NOT NULL
+  ) AS grouped
+  WHERE id <> keep_id;
+
+INSERT INTO paper_author (paper_id, author_id)
+  SELECT d.keep_id, pa.author_id
+  FROM paper_author pa JOIN paper_duplicates d ON pa.paper_id = d.id
+  ON CONFLICT DO NOTHING;
+DELETE FROM paper_author USING paper_duplicates d WHERE paper_author.paper_id = d.id;
+
+INSERT INTO paper_subject (paper_id, subject_id)
+  SELECT d.keep_id, ps.subject_id
+  FROM paper_subject ps JOIN paper_duplicates d ON ps.paper_id = d.id
+  ON CONFLICT DO NOTHING;
+DELETE FROM paper_subject USING paper_duplicates d WHERE paper_subject.paper_id = d.id;
+
+DELETE FROM paper_captions USING paper_duplicates d WHERE paper_captions.paper_id = d.id;
+
+UPDATE paper_changes SET paper_id = d.keep_id
+  FROM paper_duplicates d WHERE paper_changes.paper_id = d.id;
+
+DELETE FROM papers USING paper_duplicates d WHERE papers.id = d.id;
+
+DROP TABLE paper_duplicates;
+
+UPDATE papers SET url = 'https://arxiv.org/abs/' || arxiv_id WHERE arxiv_id IS NOT NULL;
+
+DO $$ BEGIN
+  IF NOT EXISTS (SELECT FROM pg_constraint WHERE conname = 'papers_arxiv_id_key') THEN
+    ALTER TABLE papers ADD CONSTRAINT papers_arxiv_id_key UNIQUE (arxiv_id);
+  END IF;
+END $$;
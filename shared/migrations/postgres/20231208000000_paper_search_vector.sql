@@ -0,0 +1,11 @@
+-- Weighted full-text document for the Postgres search backend. Body is
+-- truncated so that tsvector of even a badly extracted PDF stays under the
+-- 1MB limit, positions past the first pages barely affect ranking anyway
+ALTER TABLE papers ADD COLUMN IF NOT EXISTS search_vector tsvector
+  GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', title), 'A')
+      || setweight(to_tsvector('english', description), 'B')
+      || setweight(to_tsvector('english', left(body, 300000)), 'D')
+  ) STORED;
+
+CREATE INDEX IF NOT EXISTS papers_search_vector_idx ON papers USING GIN (search_vector);
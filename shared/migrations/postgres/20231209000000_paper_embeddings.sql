@@ -0,0 +1,13 @@
+CREATE EXTENSION IF NOT EXISTS vector;
+
+-- Vectors are bound to no dimension so that models can be switched, nearest
+-- neighbor queries filter by `dimension` and do an exact scan
+CREATE TABLE IF NOT EXISTS paper_embeddings (
+  paper_id INTEGER NOT NULL REFERENCES papers (id),
+  model VARCHAR NOT NULL,
+  dimension INTEGER NOT NULL,
+  -- Hash of embedded text, embedding is recomputed when it changes
+  content_hash VARCHAR NOT NULL,
+  embedding vector NOT NULL,
+  PRIMARY KEY (paper_id, model)
+);
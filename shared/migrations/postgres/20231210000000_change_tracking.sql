@@ -0,0 +1,88 @@
+-- Every insert or update of a paper takes the next value of `change_seq`, so
+-- consumers can follow changes with `WHERE change_seq > <last seen>`
+CREATE SEQUENCE IF NOT EXISTS change_seq AS BIGINT;
+
+ALTER TABLE papers
+  ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  ADD COLUMN IF NOT EXISTS change_seq BIGINT NOT NULL DEFAULT nextval('change_seq');
+
+UPDATE papers SET created_at = fetched_at, updated_at = fetched_at;
+
+CREATE INDEX IF NOT EXISTS papers_change_seq ON papers (change_seq);
+
+ALTER TABLE paper_author
+  ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  ADD COLUMN IF NOT EXISTS change_seq BIGINT NOT NULL DEFAULT nextval('change_seq');
+
+ALTER TABLE paper_subject
+  ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  ADD COLUMN IF NOT EXISTS change_seq BIGINT NOT NULL DEFAULT nextval('change_seq');
+
+-- Refetching a paper without changes only moves `fetched_at` and does not
+-- count as a change
+CREATE OR REPLACE FUNCTION papers_track_update() RETURNS trigger AS $$
+BEGIN
+  NEW.updated_at := now();
+  NEW.change_seq := nextval('change_seq');
+  RETURN NEW;
+END $$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS papers_track_update ON papers;
+CREATE TRIGGER papers_track_update BEFORE UPDATE ON papers
+  FOR EACH ROW
+  WHEN ((OLD.url, OLD.arxiv_id, OLD.source, OLD.title, OLD.description, OLD.body, OLD.status)
+    IS DISTINCT FROM (NEW.url, NEW.arxiv_id, NEW.source, NEW.title, NEW.description, NEW.body,
+      NEW.status))
+  EXECUTE FUNCTION papers_track_update();
+
+-- Authors and subjects are part of a paper, adding or removing one counts as
+-- a change of the paper. Papers inserted or already changed in the same
+-- transaction have `updated_at = now()` and are not bumped again
+CREATE OR REPLACE FUNCTION paper_links_track_change() RETURNS trigger AS $$
+BEGIN
+  UPDATE papers SET updated_at = now(), change_seq = nextval('change_seq')
+    WHERE id IN (SELECT paper_id FROM changed_links) AND updated_at <> now();
+  RETURN NULL;
+END $$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS paper_author_track_insert ON paper_author;
+CREATE TRIGGER paper_author_track_insert AFTER INSERT ON paper_author
+  REFERENCING NEW TABLE AS changed_links
+  FOR EACH STATEMENT EXECUTE FUNCTION paper_links_track_change();
+
+DROP TRIGGER IF EXISTS paper_author_track_delete ON paper_author;
+CREATE TRIGGER paper_author_track_delete AFTER DELETE ON paper_author
+  REFERENCING OLD TABLE AS changed_links
+  FOR EACH STATEMENT EXECUTE FUNCTION paper_links_track_change();
+
+DROP TRIGGER IF EXISTS paper_subject_track_insert ON paper_subject;
+CREATE TRIGGER paper_subject_track_insert AFTER INSERT ON paper_subject
+  REFERENCING NEW TABLE AS changed_links
+  FOR EACH STATEMENT EXECUTE FUNCTION paper_links_track_change();
+
+DROP TRIGGER IF EXISTS paper_subject_track_delete ON paper_subject;
+CREATE TRIGGER paper_subject_track_delete AFTER DELETE ON paper_subject
+  REFERENCING OLD TABLE AS changed_links
+  FOR EACH STATEMENT EXECUTE FUNCTION paper_links_track_change();
+
+-- Payload is a JSON object with `paper_id`, `change_seq` and `updated_at`
+CREATE OR REPLACE FUNCTION papers_notify_change() RETURNS trigger AS $$
+BEGIN
+  PERFORM pg_notify('papers_changed', json_build_object(
+    'paper_id', NEW.id,
+    'change_seq', NEW.change_seq,
+    'updated_at', NEW.updated_at
+  )::text);
+  RETURN NULL;
+END $$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS papers_notify_insert ON papers;
+CREATE TRIGGER papers_notify_insert AFTER INSERT ON papers
+  FOR EACH ROW EXECUTE FUNCTION papers_notify_change();
+
+DROP TRIGGER IF EXISTS papers_notify_update ON papers;
+CREATE TRIGGER papers_notify_update AFTER UPDATE ON papers
+  FOR EACH ROW
+  WHEN (OLD.change_seq IS DISTINCT FROM NEW.change_seq)
+  EXECUTE FUNCTION papers_notify_change();
@@ -0,0 +1,67 @@
+-- Deleting a paper deletes everything attached to it
+ALTER TABLE paper_author
+  DROP CONSTRAINT IF EXISTS paper_author_paper_id_fkey,
+  ADD CONSTRAINT paper_author_paper_id_fkey
+    FOREIGN KEY (paper_id) REFERENCES papers (id) ON DELETE CASCADE;
+
+ALTER TABLE paper_subject
+  DROP CONSTRAINT IF EXISTS paper_subject_paper_id_fkey,
+  ADD CONSTRAINT paper_subject_paper_id_fkey
+    FOREIGN KEY (paper_id) REFERENCES papers (id) ON DELETE CASCADE;
+
+ALTER TABLE paper_captions
+  DROP CONSTRAINT IF EXISTS paper_captions_paper_id_fkey,
+  ADD CONSTRAINT paper_captions_paper_id_fkey
+    FOREIGN KEY (paper_id) REFERENCES papers (id) ON DELETE CASCADE;
+
+ALTER TABLE paper_changes
+  DROP CONSTRAINT IF EXISTS paper_changes_paper_id_fkey,
+  ADD CONSTRAINT paper_changes_paper_id_fkey
+    FOREIGN KEY (paper_id) REFERENCES papers (id) ON DELETE CASCADE;
+
+ALTER TABLE paper_embeddings
+  DROP CONSTRAINT IF EXISTS paper_embeddings_paper_id_fkey,
+  ADD CONSTRAINT paper_embeddings_paper_id_fkey
+    FOREIGN KEY (paper_id) REFERENCES papers (id) ON DELETE CASCADE;
+
+-- Tombstones of deleted papers. They keep the paper from being scraped again
+-- and let consumers of the change feed learn about the deletion
+CREATE TABLE IF NOT EXISTS takedowns (
+  id SERIAL PRIMARY KEY,
+  paper_id INTEGER NOT NULL,
+  url VARCHAR NOT NULL UNIQUE,
+  arxiv_id VARCHAR UNIQUE,
+  reason TEXT NOT NULL,
+  taken_down_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  change_seq BIGINT NOT NULL DEFAULT nextval('change_seq')
+);
+
+CREATE INDEX IF NOT EXISTS takedowns_change_seq ON takedowns (change_seq);
+
+-- Payload is a JSON object with `paper_id`, `change_seq`, `updated_at` and
+-- `deleted`
+CREATE OR REPLACE FUNCTION papers_notify_change() RETURNS trigger AS $$
+BEGIN
+  PERFORM pg_notify('papers_changed', json_build_object(
+    'paper_id', NEW.id,
+    'change_seq', NEW.change_seq,
+    'updated_at', NEW.updated_at,
+    'deleted', false
+  )::text);
+  RETURN NULL;
+END $$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION takedowns_notify() RETURNS trigger AS $$
+BEGIN
+  PERFORM pg_notify('papers_changed', json_build_object(
+    'paper_id', NEW.paper_id,
+    'change_seq', NEW.change_seq,
+    'updated_at', NEW.taken_down_at,
+    'deleted', true
+  )::text);
+  RETURN NULL;
+END $$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS takedowns_notify ON takedowns;
+CREATE TRIGGER takedowns_notify AFTER INSERT ON takedowns
+  FOR EACH ROW EXECUTE FUNCTION takedowns_notify();
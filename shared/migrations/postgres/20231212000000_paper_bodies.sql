@@ -0,0 +1,52 @@
+DO $$ BEGIN
+  CREATE TYPE body_compression AS ENUM ('none', 'zstd');
+EXCEPTION
+  WHEN duplicate_object THEN NULL;
+END $$;
+
+-- Full text is kept apart from metadata so that looking papers up does not
+-- read it. The store compresses bodies with zstd unless they are short,
+-- bodies moved from `papers` stay uncompressed
+CREATE TABLE IF NOT EXISTS paper_bodies (
+  paper_id INTEGER PRIMARY KEY REFERENCES papers (id) ON DELETE CASCADE,
+  compression body_compression NOT NULL,
+  -- Size of uncompressed body in bytes
+  size INTEGER NOT NULL,
+  content BYTEA NOT NULL
+);
+
+INSERT INTO paper_bodies (paper_id, compression, size, content)
+  SELECT id, 'none', octet_length(body), convert_to(body, 'UTF8') FROM papers
+  ON CONFLICT DO NOTHING;
+
+DROP TRIGGER IF EXISTS papers_track_update ON papers;
+CREATE TRIGGER papers_track_update BEFORE UPDATE ON papers
+  FOR EACH ROW
+  WHEN ((OLD.url, OLD.arxiv_id, OLD.source, OLD.title, OLD.description, OLD.status)
+    IS DISTINCT FROM (NEW.url, NEW.arxiv_id, NEW.source, NEW.title, NEW.description, NEW.status))
+  EXECUTE FUNCTION papers_track_update();
+
+-- Compressed body can not be indexed by Postgres, so the store sets body part
+-- of the search vector (weight D) on insert and this trigger adds title and
+-- abstract to it
+ALTER TABLE papers DROP COLUMN IF EXISTS search_vector;
+ALTER TABLE papers ADD COLUMN search_vector tsvector NOT NULL DEFAULT '';
+
+CREATE OR REPLACE FUNCTION papers_search_vector() RETURNS trigger AS $$
+BEGIN
+  NEW.search_vector := setweight(to_tsvector('english', NEW.title), 'A')
+    || setweight(to_tsvector('english', NEW.description), 'B')
+    || ts_filter(NEW.search_vector, '{d}');
+  RETURN NEW;
+END $$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS papers_search_vector ON papers;
+CREATE TRIGGER papers_search_vector
+  BEFORE INSERT OR UPDATE OF title, description, search_vector ON papers
+  FOR EACH ROW EXECUTE FUNCTION papers_search_vector();
+
+UPDATE papers SET search_vector = setweight(to_tsvector('english', left(body, 300000)), 'D');
+
+ALTER TABLE papers DROP COLUMN body;
+
+CREATE INDEX IF NOT EXISTS papers_search_vector_idx ON papers USING GIN (search_vector);
@@ -0,0 +1,26 @@
+-- Papers deleted without a tombstone, e.g. empty ones which should be scraped
+-- again. Unlike takedowns they do not keep the paper out, they only let
+-- consumers of the change feed learn about the deletion
+CREATE TABLE IF NOT EXISTS paper_deletions (
+  id SERIAL PRIMARY KEY,
+  paper_id INTEGER NOT NULL,
+  deleted_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  change_seq BIGINT NOT NULL DEFAULT nextval('change_seq')
+);
+
+CREATE INDEX IF NOT EXISTS paper_deletions_change_seq ON paper_deletions (change_seq);
+
+CREATE OR REPLACE FUNCTION paper_deletions_notify() RETURNS trigger AS $$
+BEGIN
+  PERFORM pg_notify('papers_changed', json_build_object(
+    'paper_id', NEW.paper_id,
+    'change_seq', NEW.change_seq,
+    'updated_at', NEW.deleted_at,
+    'deleted', true
+  )::text);
+  RETURN NULL;
+END $$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS paper_deletions_notify ON paper_deletions;
+CREATE TRIGGER paper_deletions_notify AFTER INSERT ON paper_deletions
+  FOR EACH ROW EXECUTE FUNCTION paper_deletions_notify();
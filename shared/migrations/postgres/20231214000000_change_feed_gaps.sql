@@ -0,0 +1,8 @@
+-- Adding or removing an author or subject bumps `change_seq` of the paper
+-- only, links taking numbers of their own left gaps in the change feed
+ALTER TABLE paper_author DROP COLUMN IF EXISTS change_seq;
+ALTER TABLE paper_subject DROP COLUMN IF EXISTS change_seq;
+
+-- Changes are committed out of order, consumers look up recent ones by time
+-- to pick up those committed after they read past them
+CREATE INDEX IF NOT EXISTS papers_updated_at ON papers (updated_at);
@@ -0,0 +1,69 @@
+-- SQLite schema matching all Postgres migrations up to this version. Enums
+-- are stored as text, timestamps as RFC 3339 text and JSON as text
+
+CREATE TABLE papers (
+  id INTEGER PRIMARY KEY,
+  url TEXT NOT NULL UNIQUE,
+  arxiv_id TEXT UNIQUE,
+  source TEXT NOT NULL DEFAULT 'arxiv',
+  title TEXT NOT NULL,
+  description TEXT NOT NULL,
+  body TEXT NOT NULL,
+  fetched_at TEXT NOT NULL,
+  status TEXT NOT NULL DEFAULT 'active'
+    CHECK (status IN ('active', 'withdrawn', 'superseded'))
+);
+
+CREATE TABLE authors (
+  id INTEGER PRIMARY KEY,
+  name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE paper_author (
+  paper_id INTEGER REFERENCES papers (id),
+  author_id INTEGER REFERENCES authors (id),
+  PRIMARY KEY (paper_id, author_id)
+);
+
+CREATE TABLE subjects (
+  id INTEGER PRIMARY KEY,
+  name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE paper_subject (
+  paper_id INTEGER REFERENCES papers (id),
+  subject_id INTEGER REFERENCES subjects (id),
+  PRIMARY KEY (paper_id, subject_id)
+);
+
+CREATE TABLE paper_captions (
+  id INTEGER PRIMARY KEY,
+  paper_id INTEGER NOT NULL REFERENCES papers (id),
+  kind TEXT NOT NULL CHECK (kind IN ('figure', 'table')),
+  number TEXT NOT NULL,
+  text TEXT NOT NULL
+);
+
+CREATE INDEX paper_captions_paper_id ON paper_captions (paper_id);
+
+CREATE TABLE paper_changes (
+  id INTEGER PRIMARY KEY,
+  paper_id INTEGER NOT NULL REFERENCES papers (id),
+  changed_at TEXT NOT NULL,
+  field TEXT NOT NULL,
+  old_value TEXT,
+  new_value TEXT
+);
+
+CREATE TABLE scrape_runs (
+  id INTEGER PRIMARY KEY,
+  started_at TEXT NOT NULL,
+  finished_at TEXT NOT NULL,
+  query TEXT NOT NULL,
+  pages_scraped INTEGER NOT NULL,
+  papers_found INTEGER NOT NULL,
+  papers_stored INTEGER NOT NULL,
+  papers_failed INTEGER NOT NULL,
+  bytes_downloaded INTEGER NOT NULL,
+  failures TEXT NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Embedding is a JSON array of floats, nearest neighbors are searched in Rust
+CREATE TABLE paper_embeddings (
+  paper_id INTEGER NOT NULL REFERENCES papers (id),
+  model TEXT NOT NULL,
+  dimension INTEGER NOT NULL,
+  content_hash TEXT NOT NULL,
+  embedding TEXT NOT NULL,
+  PRIMARY KEY (paper_id, model)
+);
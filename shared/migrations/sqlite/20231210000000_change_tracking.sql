@@ -0,0 +1,85 @@
+-- SQLite has no sequences, `change_counter` holds the last issued value of
+-- change sequence shared by papers and their links
+CREATE TABLE change_counter (value INTEGER NOT NULL);
+INSERT INTO change_counter VALUES (0);
+
+-- Columns added to existing tables can not default to current time, triggers
+-- below fill them in
+ALTER TABLE papers ADD COLUMN created_at TEXT;
+ALTER TABLE papers ADD COLUMN updated_at TEXT;
+ALTER TABLE papers ADD COLUMN change_seq INTEGER;
+ALTER TABLE paper_author ADD COLUMN created_at TEXT;
+ALTER TABLE paper_author ADD COLUMN change_seq INTEGER;
+ALTER TABLE paper_subject ADD COLUMN created_at TEXT;
+ALTER TABLE paper_subject ADD COLUMN change_seq INTEGER;
+
+UPDATE papers SET created_at = fetched_at, updated_at = fetched_at, change_seq = id;
+UPDATE change_counter SET value = (SELECT coalesce(max(id), 0) FROM papers);
+UPDATE paper_author SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+  change_seq = (SELECT value FROM change_counter);
+UPDATE paper_subject SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+  change_seq = (SELECT value FROM change_counter);
+
+CREATE INDEX papers_change_seq ON papers (change_seq);
+
+CREATE TRIGGER papers_track_insert AFTER INSERT ON papers
+BEGIN
+  UPDATE change_counter SET value = value + 1;
+  UPDATE papers SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    change_seq = (SELECT value FROM change_counter)
+    WHERE id = NEW.id;
+END;
+
+-- Refetching a paper without changes only moves `fetched_at` and does not
+-- count as a change
+CREATE TRIGGER papers_track_update
+AFTER UPDATE OF url, arxiv_id, source, title, description, body, status ON papers
+WHEN (OLD.url, OLD.arxiv_id, OLD.source, OLD.title, OLD.description, OLD.body, OLD.status)
+  IS NOT (NEW.url, NEW.arxiv_id, NEW.source, NEW.title, NEW.description, NEW.body, NEW.status)
+BEGIN
+  UPDATE change_counter SET value = value + 1;
+  UPDATE papers SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    change_seq = (SELECT value FROM change_counter)
+    WHERE id = NEW.id;
+END;
+
+-- Authors and subjects are part of a paper, adding or removing one counts as
+-- a change of the paper
+CREATE TRIGGER paper_author_track_insert AFTER INSERT ON paper_author
+BEGIN
+  UPDATE change_counter SET value = value + 1;
+  UPDATE paper_author SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    change_seq = (SELECT value FROM change_counter)
+    WHERE paper_id = NEW.paper_id AND author_id = NEW.author_id;
+  UPDATE papers SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    change_seq = (SELECT value FROM change_counter)
+    WHERE id = NEW.paper_id;
+END;
+
+CREATE TRIGGER paper_author_track_delete AFTER DELETE ON paper_author
+BEGIN
+  UPDATE change_counter SET value = value + 1;
+  UPDATE papers SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    change_seq = (SELECT value FROM change_counter)
+    WHERE id = OLD.paper_id;
+END;
+
+CREATE TRIGGER paper_subject_track_insert AFTER INSERT ON paper_subject
+BEGIN
+  UPDATE change_counter SET value = value + 1;
+  UPDATE paper_subject SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    change_seq = (SELECT value FROM change_counter)
+    WHERE paper_id = NEW.paper_id AND subject_id = NEW.subject_id;
+  UPDATE papers SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    change_seq = (SELECT value FROM change_counter)
+    WHERE id = NEW.paper_id;
+END;
+
+CREATE TRIGGER paper_subject_track_delete AFTER DELETE ON paper_subject
+BEGIN
+  UPDATE change_counter SET value = value + 1;
+  UPDATE papers SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    change_seq = (SELECT value FROM change_counter)
+    WHERE id = OLD.paper_id;
+END;
@@ -0,0 +1,22 @@
+-- Foreign keys of existing tables can not be altered in SQLite, so rows
+-- attached to a paper are deleted along with it by the store itself
+
+-- Tombstones of deleted papers. They keep the paper from being scraped again
+-- and let consumers of the change feed learn about the deletion
+CREATE TABLE takedowns (
+  id INTEGER PRIMARY KEY,
+  paper_id INTEGER NOT NULL,
+  url TEXT NOT NULL UNIQUE,
+  arxiv_id TEXT UNIQUE,
+  reason TEXT NOT NULL,
+  taken_down_at TEXT NOT NULL,
+  change_seq INTEGER
+);
+
+CREATE INDEX takedowns_change_seq ON takedowns (change_seq);
+
+CREATE TRIGGER takedowns_track_insert AFTER INSERT ON takedowns
+BEGIN
+  UPDATE change_counter SET value = value + 1;
+  UPDATE takedowns SET change_seq = (SELECT value FROM change_counter) WHERE id = NEW.id;
+END;
@@ -0,0 +1,27 @@
+-- Full text is kept apart from metadata so that looking papers up does not
+-- read it. The store compresses bodies with zstd unless they are short,
+-- bodies moved from `papers` stay uncompressed
+CREATE TABLE paper_bodies (
+  paper_id INTEGER PRIMARY KEY REFERENCES papers (id),
+  compression TEXT NOT NULL CHECK (compression IN ('none', 'zstd')),
+  -- Size of uncompressed body in bytes
+  size INTEGER NOT NULL,
+  content BLOB NOT NULL
+);
+
+INSERT INTO paper_bodies (paper_id, compression, size, content)
+  SELECT id, 'none', length(CAST(body AS BLOB)), CAST(body AS BLOB) FROM papers;
+
+DROP TRIGGER papers_track_update;
+CREATE TRIGGER papers_track_update
+AFTER UPDATE OF url, arxiv_id, source, title, description, status ON papers
+WHEN (OLD.url, OLD.arxiv_id, OLD.source, OLD.title, OLD.description, OLD.status)
+  IS NOT (NEW.url, NEW.arxiv_id, NEW.source, NEW.title, NEW.description, NEW.status)
+BEGIN
+  UPDATE change_counter SET value = value + 1;
+  UPDATE papers SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    change_seq = (SELECT value FROM change_counter)
+    WHERE id = NEW.id;
+END;
+
+ALTER TABLE papers DROP COLUMN body;
@@ -0,0 +1,17 @@
+-- Papers deleted without a tombstone, e.g. empty ones which should be scraped
+-- again. Unlike takedowns they do not keep the paper out, they only let
+-- consumers of the change feed learn about the deletion
+CREATE TABLE paper_deletions (
+  id INTEGER PRIMARY KEY,
+  paper_id INTEGER NOT NULL,
+  deleted_at TEXT NOT NULL,
+  change_seq INTEGER
+);
+
+CREATE INDEX paper_deletions_change_seq ON paper_deletions (change_seq);
+
+CREATE TRIGGER paper_deletions_track_insert AFTER INSERT ON paper_deletions
+BEGIN
+  UPDATE change_counter SET value = value + 1;
+  UPDATE paper_deletions SET change_seq = (SELECT value FROM change_counter) WHERE id = NEW.id;
+END;
@@ -0,0 +1,27 @@
+-- Adding or removing an author or subject bumps `change_seq` of the paper
+-- only. Triggers are recreated first, a column used by a trigger can not be
+-- dropped
+DROP TRIGGER paper_author_track_insert;
+CREATE TRIGGER paper_author_track_insert AFTER INSERT ON paper_author
+BEGIN
+  UPDATE change_counter SET value = value + 1;
+  UPDATE paper_author SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+    WHERE paper_id = NEW.paper_id AND author_id = NEW.author_id;
+  UPDATE papers SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    change_seq = (SELECT value FROM change_counter)
+    WHERE id = NEW.paper_id;
+END;
+
+DROP TRIGGER paper_subject_track_insert;
+CREATE TRIGGER paper_subject_track_insert AFTER INSERT ON paper_subject
+BEGIN
+  UPDATE change_counter SET value = value + 1;
+  UPDATE paper_subject SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+    WHERE paper_id = NEW.paper_id AND subject_id = NEW.subject_id;
+  UPDATE papers SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+    change_seq = (SELECT value FROM change_counter)
+    WHERE id = NEW.paper_id;
+END;
+
+ALTER TABLE paper_author DROP COLUMN change_seq;
+ALTER TABLE paper_subject DROP COLUMN change_seq;
@@ -0,0 +1,319 @@
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("invalid arXiv identifier {0:?}")]
+    InvalidId(String),
+    #[error("url {0:?} does not point to an arXiv paper")]
+    InvalidUrl(String),
+}
+
+/// Canonical arXiv identifier, either new-style (`2101.00001v2`) or old-style
+/// (`hep-th/9901001`). Subject class of old-style identifiers
+/// (`math.GT/0309136`) is not part of identity and is dropped
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ArxivId {
+    id: String,
+    version: Option<u32>,
+}
+
+impl ArxivId {
+    /// Extract identifier from `abs`, `pdf` or `html` url on any arXiv host
+    pub fn from_url(url: &str) -> Result<Self, ParseError> {
+        let invalid = || ParseError::InvalidUrl(url.to_string());
+
+        let path = url.split(['?', '#']).next().unwrap_or_default();
+        let (_, id) = ["/abs/", "/pdf/", "/html/"]
+            .iter()
+            .find_map(|kind| path.split_once(kind))
+            .ok_or_else(invalid)?;
+        let id = id.trim_end_matches('/').trim_end_matches(".pdf");
+
+        id.parse().map_err(|_| invalid())
+    }
+
+    /// Identifier without version
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn version(&self) -> Option<u32> {
+        self.version
+    }
+
+    /// Same paper regardless of version, used as key in database
+    pub fn unversioned(&self) -> Self {
+        Self {
+            id: self.id.clone(),
+            version: None,
+        }
+    }
+
+    pub fn abs_url(&self) -> String {
+        format!("https://arxiv.org/abs/{self}")
+    }
+
+    pub fn pdf_url(&self) -> String {
+        format!("https://arxiv.org/pdf/{self}")
+    }
+
+    pub fn html_url(&self) -> String {
+        format!("https://arxiv.org/html/{self}")
+    }
+}
+
+impl FromStr for ArxivId {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseError::InvalidId(s.to_string());
+
+        let s = s.trim();
+        let s = s
+            .strip_prefix("arXiv:")
+            .or_else(|| s.strip_prefix("arxiv:"))
+            .unwrap_or(s);
+
+        let (id, version) = match s.rsplit_once('v') {
+            Some((id, version)) if is_digits(version) => {
+                let version = version.parse::<u32>().map_err(|_| invalid())?;
+                if version == 0 {
+                    return Err(invalid());
+                }
+                (id, Some(version))
+            }
+            _ => (s, None),
+        };
+
+        let id = match id.split_once('/') {
+            Some((archive, number)) => parse_old_style(archive, number),
+            None => parse_new_style(id),
+        }
+        .ok_or_else(invalid)?;
+
+        Ok(Self { id, version })
+    }
+}
+
+impl fmt::Display for ArxivId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.id)?;
+        if let Some(version) = self.version {
+            write!(f, "v{version}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Serialized as string, e.g. `"2101.00001v2"`
+impl serde::Serialize for ArxivId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ArxivId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Stored as text in both Postgres and SQLite
+impl<DB: sqlx::Database> sqlx::Type<DB> for ArxivId
+where
+    String: sqlx::Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <String as sqlx::Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <String as sqlx::Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'q, DB: sqlx::Database> sqlx::Encode<'q, DB> for ArxivId
+where
+    String: sqlx::Encode<'q, DB>,
+{
+    fn encode_by_ref(
+        &self,
+        buf: &mut <DB as sqlx::database::HasArguments<'q>>::ArgumentBuffer,
+    ) -> sqlx::encode::IsNull {
+        <String as sqlx::Encode<'q, DB>>::encode(self.to_string(), buf)
+    }
+}
+
+impl<'r, DB: sqlx::Database> sqlx::Decode<'r, DB> for ArxivId
+where
+    &'r str: sqlx::Decode<'r, DB>,
+{
+    fn decode(
+        value: <DB as sqlx::database::HasValueRef<'r>>::ValueRef,
+    ) -> Result<Self, sqlx::error::BoxDynError> {
+        Ok(<&str as sqlx::Decode<DB>>::decode(value)?.parse()?)
+    }
+}
+
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_valid_month(yymm: &str) -> bool {
+    matches!(yymm[2..4].parse::<u32>(), Ok(1..=12))
+}
+
+/// `YYMM.NNNN` until 2014, `YYMM.NNNNN` since 2015
+fn parse_new_style(id: &str) -> Option<String> {
+    let (yymm, number) = id.split_once('.')?;
+    if !is_digits(yymm) || yymm.len() != 4 || !is_digits(number) || !is_valid_month(yymm) {
+        return None;
+    }
+
+    let year = yymm[..2].parse::<u32>().ok()?;
+    let valid_length = match year {
+        7..=14 => number.len() == 4,
+        15.. => number.len() == 5,
+        _ => false,
+    };
+
+    valid_length.then(|| id.to_string())
+}
+
+/// `archive(.XX)/YYMMNNN`, used until March 2007
+fn parse_old_style(archive: &str, number: &str) -> Option<String> {
+    let archive = match archive.split_once('.') {
+        Some((archive, class))
+            if class.len() == 2 && class.bytes().all(|b| b.is_ascii_uppercase()) =>
+        {
+            archive
+        }
+        Some(_) => return None,
+        None => archive,
+    };
+
+    let valid_archive = !archive.is_empty()
+        && archive.starts_with(|c: char| c.is_ascii_lowercase())
+        && archive.bytes().all(|b| b.is_ascii_lowercase() || b == b'-');
+    if !valid_archive || !is_digits(number) || number.len() != 7 || !is_valid_month(number) {
+        return None;
+    }
+
+    Some(format!("{archive}/{number}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> ArxivId {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_new_style() {
+        let id = parse("2101.00001v2");
+        assert_eq!(id.id(), "2101.00001");
+        assert_eq!(id.version(), Some(2));
+        assert_eq!(id.to_string(), "2101.00001v2");
+
+        assert_eq!(parse("0704.0001").id(), "0704.0001");
+        assert_eq!(parse("1412.9999").version(), None);
+        assert_eq!(parse("arXiv:2312.12345").id(), "2312.12345");
+        assert_eq!(parse(" arxiv:2312.12345v1 ").to_string(), "2312.12345v1");
+    }
+
+    #[test]
+    fn parses_old_style() {
+        let id = parse("hep-th/9901001v3");
+        assert_eq!(id.id(), "hep-th/9901001");
+        assert_eq!(id.version(), Some(3));
+
+        assert_eq!(parse("math.GT/0309136").id(), "math/0309136");
+        assert_eq!(parse("cond-mat/0703001").to_string(), "cond-mat/0703001");
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        for s in [
+            "",
+            "2101.00001v0",
+            "2101.0001",
+            "1412.00001",
+            "2113.00001",
+            "2100.00001",
+            "0612.0001",
+            "21010.0001",
+            "2101.000a1",
+            "2101.00001v",
+            "hep-th/990100",
+            "hep-th/9913001",
+            "Hep-th/9901001",
+            "math.gt/0309136",
+            "math.GTX/0309136",
+            "/9901001",
+            "not an id",
+        ] {
+            assert_eq!(
+                s.parse::<ArxivId>(),
+                Err(ParseError::InvalidId(s.to_string())),
+                "{s:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn from_url_on_any_host() {
+        for url in [
+            "https://arxiv.org/abs/2101.00001v2",
+            "http://arxiv.org/abs/2101.00001v2/",
+            "https://export.arxiv.org/abs/2101.00001v2",
+            "https://arxiv.org/pdf/2101.00001v2.pdf",
+            "https://export.arxiv.org/pdf/2101.00001v2",
+            "https://arxiv.org/html/2101.00001v2",
+            "https://arxiv.org/abs/2101.00001v2?context=cs#abstract",
+        ] {
+            assert_eq!(ArxivId::from_url(url), Ok(parse("2101.00001v2")), "{url:?}");
+        }
+
+        assert_eq!(
+            ArxivId::from_url("https://export.arxiv.org/abs/hep-th/9901001").unwrap(),
+            parse("hep-th/9901001")
+        );
+    }
+
+    #[test]
+    fn from_url_rejects_other_urls() {
+        for url in [
+            "https://arxiv.org/list/cs/recent",
+            "https://arxiv.org/abs/",
+            "https://arxiv.org/abs/not-an-id",
+            "https://example.com/paper.pdf",
+        ] {
+            assert_eq!(
+                ArxivId::from_url(url),
+                Err(ParseError::InvalidUrl(url.to_string())),
+                "{url:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn unversioned_drops_version() {
+        let id = parse("2101.00001v2");
+        assert_eq!(id.unversioned(), parse("2101.00001"));
+        assert_eq!(id.unversioned().to_string(), "2101.00001");
+        assert_eq!(
+            id.unversioned().abs_url(),
+            "https://arxiv.org/abs/2101.00001"
+        );
+        assert_eq!(id.unversioned(), parse("2101.00001v5").unversioned());
+        assert_eq!(
+            parse("hep-th/9901001v1").unversioned(),
+            parse("hep-th/9901001")
+        );
+    }
+}
@@ -1,212 +1,807 @@
-use sqlx::postgres::PgPoolOptions;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::models::{self, NewAuthor, NewPaper, NewSubject};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+
+use crate::arxiv_id::ArxivId;
+use crate::models::{
+    self, BodyCompression, IntegrityIssue, NewAuthor, NewPaper, NewPaperFull, NewScrapeRun,
+    NewSubject, PaperChange, PaperFilter, PaperMetadataUpdate, PaperRef, PaperStatus,
+};
+
+mod postgres;
+mod sqlite;
+
+pub use postgres::PostgresStore;
+pub use sqlite::SqliteStore;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("database error")]
-    Sqlx(#[from] sqlx::Error),
+    Sqlx(#[source] sqlx::Error),
+
+    #[error("row not found")]
+    NotFound,
+
+    /// Unique or foreign key constraint violated, e.g. by a concurrent insert
+    #[error("conflicting database write")]
+    Conflict(#[source] sqlx::Error),
+
+    #[error("database connection failed")]
+    Connection(#[source] sqlx::Error),
+
+    /// No connection became free in time or database was too busy
+    #[error("database timed out")]
+    Timeout(#[source] sqlx::Error),
+
+    #[error("migration error")]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+
+    #[error("unsupported database url {0:?}, expected postgres:// or sqlite:")]
+    UnsupportedUrl(String),
+
+    #[error("{0} is not supported by this database")]
+    Unsupported(&'static str),
+
+    #[error("malformed change notification {0:?}")]
+    Notification(String),
+
+    #[error("corrupted body of paper {0}")]
+    Body(models::Id, #[source] std::io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-pub struct DBConnection {
-    pool: sqlx::Pool<sqlx::Postgres>,
+impl Error {
+    /// Whether the same operation may succeed if retried later
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Error::Connection(_) | Error::Timeout(_))
+    }
 }
 
-impl DBConnection {
-    pub async fn new(db_url: &str) -> Result<DBConnection> {
-        Ok(DBConnection {
-            pool: PgPoolOptions::new().connect(db_url).await?,
-        })
-    }
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        use sqlx::error::ErrorKind;
 
-    pub async fn get_all_papers(&mut self) -> Result<Vec<models::Paper>> {
-        sqlx::query_as!(models::Paper, "SELECT * FROM papers")
-            .fetch_all(&self.pool)
-            .await
-            .map_err(|e| e.into())
+        match &e {
+            sqlx::Error::RowNotFound => Error::NotFound,
+            sqlx::Error::PoolTimedOut => Error::Timeout(e),
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed => Error::Connection(e),
+            sqlx::Error::Database(db_error) => match db_error.kind() {
+                ErrorKind::UniqueViolation | ErrorKind::ForeignKeyViolation => Error::Conflict(e),
+                // NOTE: Postgres `query_canceled` on statement timeout and
+                // extended codes of SQLite `SQLITE_BUSY`
+                _ if matches!(
+                    db_error.code().as_deref(),
+                    Some("57014" | "5" | "261" | "517" | "773")
+                ) =>
+                {
+                    Error::Timeout(e)
+                }
+                _ => Error::Sqlx(e),
+            },
+            _ => Error::Sqlx(e),
+        }
     }
+}
+
+/// Bodies shorter than this are stored uncompressed, compressing them saves
+/// next to nothing
+const BODY_COMPRESSION_MIN_SIZE: usize = 1024;
+
+/// zstd level of stored bodies, higher levels barely shrink plain text more
+const BODY_COMPRESSION_LEVEL: i32 = 3;
+
+/// Settings of the connection pool shared by all clones of a store
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    /// How long to wait for a free connection before failing
+    pub acquire_timeout: Duration,
+    /// Close connections which were idle for this long
+    pub idle_timeout: Option<Duration>,
+}
 
-    pub async fn count_papers(&mut self) -> Result<i64> {
-        sqlx::query_scalar!("SELECT COUNT(*) FROM papers")
-            .fetch_one(&self.pool)
-            .await
-            .map(|r| r.unwrap()) // TODO: not pretty
-            .map_err(|e| e.into())
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(600)),
+        }
     }
+}
 
-    pub async fn get_paper(&mut self, desired_id: i32) -> Result<models::Paper> {
-        sqlx::query_as!(
-            models::Paper,
-            "SELECT * FROM papers WHERE id = $1",
-            desired_id
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| e.into())
-    }
-
-    pub async fn get_paper_authors(
-        &mut self,
-        desired_paper_id: i32,
-    ) -> Result<Vec<models::Author>> {
-        sqlx::query_as!(
-            models::Author,
-            "SELECT authors.id, authors.name
-                FROM authors
-            JOIN paper_author ON authors.id = paper_author.author_id
-                WHERE paper_author.paper_id = $1",
-            desired_paper_id
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| e.into())
+/// Storage of papers and everything scraped along with them
+#[async_trait::async_trait]
+pub trait PaperStore: Send + Sync {
+    /// Bring schema up to date, applies only migrations which were not
+    /// applied yet
+    async fn migrate(&self) -> Result<()>;
+
+    async fn get_all_papers(&self) -> Result<Vec<models::PaperSummary>>;
+
+    /// Up to `limit` papers with id greater than `after_id` ordered by id,
+    /// single page of keyset pagination over the corpus. Bodies are loaded
+    /// along with papers
+    async fn get_papers_page(
+        &self,
+        after_id: Option<models::Id>,
+        limit: i64,
+    ) -> Result<Vec<models::Paper>>;
+
+    /// Every paper with its authors and captions ordered by id. Papers are
+    /// fetched `page_size` at a time, so at most one page is held in memory
+    fn stream_papers(&self, page_size: i64) -> BoxStream<'_, Result<models::PaperWithAuthors>> {
+        stream::try_unfold(Some(None), move |cursor| async move {
+            let Some(after_id) = cursor else {
+                return Ok(None);
+            };
+            let papers = self.get_papers_page(after_id, page_size).await?;
+            let Some(last_id) = papers.last().map(|p| p.id) else {
+                return Ok(None);
+            };
+            let page = with_authors(self, papers).await?;
+
+            let next = (page.len() as i64 == page_size).then_some(Some(last_id));
+            Ok::<_, Error>(Some((page, next)))
+        })
+        .map_ok(|page| stream::iter(page.into_iter().map(Ok)))
+        .try_flatten()
+        .boxed()
     }
 
-    pub async fn paper_exists(&mut self, desired_url: &str) -> Result<bool> {
-        sqlx::query_scalar!(
-            "SELECT EXISTS(SELECT * FROM papers WHERE url = $1)",
-            desired_url
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map(|r| r.unwrap()) // TODO: not pretty
-        .map_err(|e| e.into())
+    async fn count_papers(&self) -> Result<i64>;
+
+    /// Paper with its body, `None` when there is no such paper
+    async fn get_paper(&self, desired_id: models::Id) -> Result<Option<models::Paper>>;
+
+    /// Paper with its authors and subjects, `None` when there is no such paper
+    async fn get_paper_full(&self, paper_id: models::Id) -> Result<Option<models::PaperFull>> {
+        Ok(self.get_papers_full(&[paper_id]).await?.remove(&paper_id))
     }
 
-    pub async fn insert_paper(
+    /// Full text of given papers in one query, papers without body are absent
+    /// from the map
+    async fn get_paper_bodies(
         &self,
-        new_paper: NewPaper,
-        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
-    ) -> Result<models::Id> {
-        log::trace!("DB: inserting new paper {:?}", new_paper.url);
-        Ok(sqlx::query_scalar!(
-            "INSERT INTO papers (url, title, description, body)
-                VALUES ($1, $2, $3, $4)
-                RETURNING id",
-            new_paper.url,
-            new_paper.title,
-            new_paper.description,
-            new_paper.body,
-        )
-        .fetch_one(&mut **tx)
-        .await?)
-    }
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, String>>;
+
+    async fn get_paper_authors(&self, desired_paper_id: models::Id) -> Result<Vec<models::Author>>;
+
+    async fn get_paper_captions(&self, paper_id: models::Id) -> Result<Vec<models::Caption>>;
 
-    pub async fn insert_author(
+    /// Papers with given ids in one query without their bodies, ids which do
+    /// not exist are absent from the map
+    async fn get_papers_by_ids(
         &self,
-        new_author: NewAuthor,
-        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
-    ) -> Result<models::Id> {
-        log::trace!("DB: inserting new author {:?}", new_author);
-
-        sqlx::query!(
-            "INSERT INTO authors (name)
-                    VALUES ($1)
-                    ON CONFLICT(name) DO NOTHING",
-            new_author.name
-        )
-        .execute(&mut **tx)
-        .await?;
+        ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, models::PaperSummary>>;
 
-        Ok(sqlx::query_scalar!(
-            "SELECT authors.id FROM authors WHERE name = $1",
-            new_author.name
-        )
-        .fetch_one(&mut **tx)
-        .await?)
-    }
+    /// Papers with given ids along with their authors and subjects in one
+    /// query, ids which do not exist are absent from the map
+    async fn get_papers_full(
+        &self,
+        ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, models::PaperFull>>;
 
-    pub async fn insert_subject(
+    /// Papers with given ids along with their bodies, authors and captions
+    /// ordered by id, ids which do not exist are skipped
+    async fn get_papers_with_authors(
         &self,
-        new_subject: NewSubject,
-        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
-    ) -> Result<models::Id> {
-        log::trace!("DB: inserting new subject {:?}", new_subject.name);
-
-        sqlx::query!(
-            "INSERT INTO subjects (name)
-                    VALUES ($1)
-                    ON CONFLICT(name) DO NOTHING",
-            new_subject.name
-        )
-        .execute(&mut **tx)
-        .await?;
+        ids: &[models::Id],
+    ) -> Result<Vec<models::PaperWithAuthors>> {
+        let mut papers = self
+            .get_papers_by_ids(ids)
+            .await?
+            .into_values()
+            .collect::<Vec<_>>();
+        papers.sort_by_key(|p| p.id);
 
-        Ok(sqlx::query_scalar!(
-            "SELECT subjects.id FROM subjects WHERE name = $1",
-            new_subject.name
-        )
-        .fetch_one(&mut **tx)
-        .await?)
+        let paper_ids = papers.iter().map(|p| p.id).collect::<Vec<_>>();
+        let bodies = self.get_paper_bodies(&paper_ids).await?;
+
+        with_authors(self, with_bodies(papers, bodies)).await
     }
 
-    pub async fn set_paper_author(
-        &mut self,
-        paper_id: models::Id,
-        author_id: models::Id,
-        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    /// Authors of each of given papers in one query, papers without authors
+    /// are absent from the map
+    async fn get_authors_for_papers(
+        &self,
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, Vec<models::Author>>>;
+
+    /// Subjects of each of given papers in one query, papers without subjects
+    /// are absent from the map
+    async fn get_subjects_for_papers(
+        &self,
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, Vec<models::Subject>>>;
+
+    /// Captions of each of given papers in one query, ordered as in the paper
+    async fn get_captions_for_papers(
+        &self,
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, Vec<models::Caption>>>;
+
+    /// Whether paper with `desired_url` is stored or was taken down, either
+    /// way it should not be scraped again
+    async fn paper_exists(&self, desired_url: &str) -> Result<bool>;
+
+    async fn insert_paper_full(
+        &self,
+        paper: NewPaper,
+        authors: Vec<NewAuthor>,
+        subjects: Vec<NewSubject>,
     ) -> Result<()> {
-        log::trace!(
-            "DB: inserting author {:?} for paper {:?}",
-            author_id,
-            paper_id
-        );
-        sqlx::query!(
-            "INSERT INTO paper_author (paper_id, author_id)
-                VALUES ($1, $2)",
-            paper_id,
-            author_id
-        )
-        .execute(&mut **tx)
+        self.insert_papers_full(vec![NewPaperFull {
+            paper,
+            authors,
+            subjects,
+            captions: Vec::new(),
+        }])
         .await?;
 
         Ok(())
     }
 
-    pub async fn set_paper_subject(
-        &mut self,
+    /// Insert papers with their authors, subjects and captions. Papers which
+    /// url or arXiv identifier is already in the database or was taken down
+    /// are skipped. Returns urls of inserted papers.
+    async fn insert_papers_full(&self, papers: Vec<NewPaperFull>) -> Result<HashSet<String>>;
+
+    async fn insert_scrape_run(&self, run: NewScrapeRun) -> Result<models::Id>;
+
+    async fn select_papers(&self, filter: &PaperFilter) -> Result<Vec<PaperRef>>;
+
+    /// Stored embeddings of given papers computed by `model`
+    async fn get_embeddings(
+        &self,
+        model: &str,
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, models::PaperEmbedding>>;
+
+    /// Insert embeddings replacing ones of the same paper and model
+    async fn upsert_embeddings(&self, embeddings: Vec<models::PaperEmbedding>) -> Result<()>;
+
+    /// Papers which embeddings computed by `model` are closest to `embedding`,
+    /// closest first. Score is cosine similarity
+    async fn nearest_papers(
+        &self,
+        model: &str,
+        embedding: &[f32],
+        limit: i64,
+    ) -> Result<Vec<models::SearchHit>>;
+
+    /// Papers inserted or changed after change `seq` ordered by change, at most
+    /// `limit` of them. Every paper is listed once with its latest change.
+    /// Concurrent transactions may commit changes out of order, consumers
+    /// catch up on those with [`PaperStore::recent_changes`]
+    async fn changes_since(&self, seq: i64, limit: i64) -> Result<Vec<models::ChangeEvent>>;
+
+    /// Changes up to change `seq` made by transactions started at `since` or
+    /// later, ordered by change. A transaction which committed after a reader
+    /// went past change `seq` is listed here, as long as it started at `since`
+    async fn recent_changes(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+        seq: i64,
+    ) -> Result<Vec<models::ChangeEvent>>;
+
+    /// Stream of changes as they are committed, starts with changes committed
+    /// after subscribing. Events may come out of order, use
+    /// [`PaperStore::changes_since`] to catch up after reconnecting
+    async fn subscribe_changes(&self) -> Result<BoxStream<'static, Result<models::ChangeEvent>>>;
+
+    /// Delete paper with everything attached to it, leaving a tombstone with
+    /// `reason` which keeps the paper from being inserted again. Returns
+    /// `None` when there is no such paper
+    async fn take_down_paper(
+        &self,
         paper_id: models::Id,
-        subject_id: models::Id,
-        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
-    ) -> Result<()> {
-        log::trace!(
-            "DB: inserting subject {:?} for paper {:?}",
-            subject_id,
-            paper_id
-        );
-        sqlx::query!(
-            "INSERT INTO paper_subject (paper_id, subject_id) VALUES ($1, $2)",
+        reason: &str,
+    ) -> Result<Option<models::Takedown>>;
+
+    /// Every tombstone, oldest first
+    async fn get_takedowns(&self) -> Result<Vec<models::Takedown>>;
+
+    /// Insert tombstones of papers deleted from another database, e.g. ones
+    /// restored from a dump. Their `paper_id` is 0 and tombstones of already
+    /// taken down urls or arXiv identifiers are skipped. Stored papers are not
+    /// deleted. Returns number of inserted tombstones
+    async fn insert_takedowns(&self, takedowns: Vec<models::NewTakedown>) -> Result<u64>;
+
+    /// Delete papers with everything attached to them without leaving a
+    /// tombstone, so they are scraped again. Deletions are still listed by
+    /// [`PaperStore::changes_since`]. Returns number of deleted papers
+    async fn delete_papers(&self, paper_ids: &[models::Id]) -> Result<u64>;
+
+    /// Find orphans, papers with empty title or body, duplicate papers and,
+    /// when `embedding_model` is set, embeddings computed by other models
+    async fn check_integrity(&self, embedding_model: Option<&str>) -> Result<Vec<IntegrityIssue>>;
+
+    /// Delete authors and subjects without papers, returns number of deleted
+    /// rows
+    async fn delete_orphans(&self) -> Result<u64>;
+
+    /// Delete embeddings computed by any model but `model`, returns number of
+    /// deleted embeddings
+    async fn delete_stale_embeddings(&self, model: &str) -> Result<u64>;
+
+    /// Full-text search with `query` in web search syntax (`"quoted phrase"`,
+    /// `or`, `-excluded`), best matches first. Title weighs more than abstract
+    /// and abstract more than body. Withdrawn papers are left out unless
+    /// `include_withdrawn` is set
+    async fn search_papers(
+        &self,
+        query: &str,
+        limit: i64,
+        include_withdrawn: bool,
+    ) -> Result<Vec<models::SearchHit>>;
+
+    /// Replace metadata of existing paper, touching only what differs and
+    /// recording every difference in `paper_changes`. Only `fetched_at` is
+    /// written when nothing differs. Fails with
+    /// [`Error::NotFound`] when there is no such paper
+    async fn update_paper_metadata(
+        &self,
+        paper_id: models::Id,
+        update: PaperMetadataUpdate,
+    ) -> Result<Vec<PaperChange>>;
+}
+
+/// Cheaply cloneable handle to a store, clones share one connection pool
+pub type DBConnection = Arc<dyn PaperStore>;
+
+/// Connect to the store `db_url` points to, `postgres://` or `postgresql://`
+/// for Postgres and `sqlite:` for SQLite
+pub async fn connect(db_url: &str, pool: &PoolConfig) -> Result<DBConnection> {
+    if db_url.starts_with("postgres://") || db_url.starts_with("postgresql://") {
+        Ok(Arc::new(PostgresStore::new(db_url, pool).await?))
+    } else if db_url.starts_with("sqlite:") {
+        Ok(Arc::new(SqliteStore::new(db_url, pool).await?))
+    } else {
+        Err(Error::UnsupportedUrl(db_url.to_string()))
+    }
+}
+
+/// Attach authors and captions to papers
+async fn with_authors<S: PaperStore + ?Sized>(
+    store: &S,
+    papers: Vec<models::Paper>,
+) -> Result<Vec<models::PaperWithAuthors>> {
+    let paper_ids = papers.iter().map(|p| p.id).collect::<Vec<_>>();
+    let mut authors = store.get_authors_for_papers(&paper_ids).await?;
+    let mut captions = store.get_captions_for_papers(&paper_ids).await?;
+
+    Ok(papers
+        .into_iter()
+        .map(|paper| models::PaperWithAuthors {
+            authors: authors.remove(&paper.id).unwrap_or_default(),
+            captions: captions.remove(&paper.id).unwrap_or_default(),
+            paper,
+        })
+        .collect())
+}
+
+/// Attach bodies to papers, papers without body get an empty one
+fn with_bodies(
+    papers: Vec<models::PaperSummary>,
+    mut bodies: HashMap<models::Id, String>,
+) -> Vec<models::Paper> {
+    papers
+        .into_iter()
+        .map(|paper| {
+            let body = bodies.remove(&paper.id).unwrap_or_default();
+            paper.with_body(body)
+        })
+        .collect()
+}
+
+/// Body as stored in `paper_bodies`: compression, uncompressed size and content
+fn encode_body(body: &str) -> (BodyCompression, i32, Vec<u8>) {
+    let size = body.len() as i32;
+    if body.len() >= BODY_COMPRESSION_MIN_SIZE {
+        // NOTE: compressing into memory can only fail on allocation
+        if let Ok(content) = zstd::bulk::compress(body.as_bytes(), BODY_COMPRESSION_LEVEL) {
+            return (BodyCompression::Zstd, size, content);
+        }
+    }
+    (BodyCompression::None, size, body.as_bytes().to_vec())
+}
+
+fn decode_body(
+    paper_id: models::Id,
+    compression: BodyCompression,
+    size: i32,
+    content: Vec<u8>,
+) -> Result<String> {
+    let bytes = match compression {
+        BodyCompression::None => content,
+        BodyCompression::Zstd => {
+            zstd::bulk::decompress(&content, size as usize).map_err(|e| Error::Body(paper_id, e))?
+        }
+    };
+    String::from_utf8(bytes).map_err(|e| {
+        Error::Body(
             paper_id,
-            subject_id,
+            std::io::Error::new(std::io::ErrorKind::InvalidData, e),
         )
-        .execute(&mut **tx)
-        .await?;
+    })
+}
 
-        Ok(())
+/// Papers stored more than once under different urls or versions of the
+/// same arXiv identifier, the paper with the lowest id is the original
+fn find_duplicates(mut papers: Vec<PaperRef>) -> Vec<IntegrityIssue> {
+    papers.sort_by_key(|p| p.id);
+
+    let mut originals = HashMap::new();
+    let mut issues = Vec::new();
+    for paper in papers {
+        let key = match ArxivId::from_url(&paper.url) {
+            Ok(arxiv_id) => arxiv_id.unversioned().to_string(),
+            Err(_) => paper.url.clone(),
+        };
+        match originals.get(&key) {
+            Some(&original_id) => issues.push(IntegrityIssue::DuplicatePaper {
+                paper_id: paper.id,
+                url: paper.url,
+                original_id,
+            }),
+            None => {
+                originals.insert(key, paper.id);
+            }
+        }
     }
 
-    pub async fn insert_paper_full(
-        &mut self,
-        paper: NewPaper,
-        authors: Vec<NewAuthor>,
-        subjects: Vec<NewSubject>,
-    ) -> Result<()> {
-        let mut tx = self.pool.begin().await?;
+    issues
+}
 
-        let paper_id = self.insert_paper(paper, &mut tx).await?;
+/// Drop papers which url or arXiv identifier repeats earlier in the batch
+fn dedup_papers(papers: &mut Vec<NewPaperFull>) {
+    let (mut seen_urls, mut seen_arxiv_ids) = (HashSet::new(), HashSet::new());
+    papers.retain(|p| {
+        let new_arxiv_id = match &p.paper.arxiv_id {
+            Some(id) => seen_arxiv_ids.insert(id.clone()),
+            None => true,
+        };
+        seen_urls.insert(p.paper.url.clone()) && new_arxiv_id
+    });
+}
 
-        for author in authors {
-            let author_id = self.insert_author(author, &mut tx).await?;
-            self.set_paper_author(paper_id, author_id, &mut tx).await?;
+/// Metadata of a paper as currently stored
+struct StoredMetadata {
+    title: String,
+    description: String,
+    status: PaperStatus,
+    authors: HashSet<String>,
+    subjects: HashSet<String>,
+}
+
+/// What has to change to turn stored metadata into the updated one
+struct MetadataDiff {
+    changes: Vec<PaperChange>,
+    /// Whether title, description or status differ
+    paper_changed: bool,
+    added_authors: Vec<String>,
+    removed_authors: Vec<String>,
+    added_subjects: Vec<String>,
+    removed_subjects: Vec<String>,
+}
+
+fn diff_metadata(current: StoredMetadata, update: &PaperMetadataUpdate) -> MetadataDiff {
+    let mut changes = Vec::new();
+    for (field, old, new) in [
+        ("title", current.title, &update.title),
+        ("description", current.description, &update.description),
+    ] {
+        if &old != new {
+            changes.push(PaperChange {
+                field: field.to_string(),
+                old_value: Some(old),
+                new_value: Some(new.clone()),
+            });
+        }
+    }
+    if current.status != update.status {
+        changes.push(PaperChange {
+            field: "status".to_string(),
+            old_value: Some(current.status.to_string()),
+            new_value: Some(update.status.to_string()),
+        });
+    }
+    let paper_changed = !changes.is_empty();
+
+    let new_authors = update
+        .authors
+        .iter()
+        .map(|a| a.name.clone())
+        .collect::<HashSet<_>>();
+    let added_authors = new_authors
+        .difference(&current.authors)
+        .cloned()
+        .collect::<Vec<_>>();
+    let removed_authors = current
+        .authors
+        .difference(&new_authors)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let new_subjects = update
+        .subjects
+        .iter()
+        .map(|s| s.name.clone())
+        .collect::<HashSet<_>>();
+    let added_subjects = new_subjects
+        .difference(&current.subjects)
+        .cloned()
+        .collect::<Vec<_>>();
+    let removed_subjects = current
+        .subjects
+        .difference(&new_subjects)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for (field, added, removed) in [
+        ("author", &added_authors, &removed_authors),
+        ("subject", &added_subjects, &removed_subjects),
+    ] {
+        changes.extend(added.iter().map(|name| PaperChange {
+            field: field.to_string(),
+            old_value: None,
+            new_value: Some(name.clone()),
+        }));
+        changes.extend(removed.iter().map(|name| PaperChange {
+            field: field.to_string(),
+            old_value: Some(name.clone()),
+            new_value: None,
+        }));
+    }
+
+    MetadataDiff {
+        changes,
+        paper_changed,
+        added_authors,
+        removed_authors,
+        added_subjects,
+        removed_subjects,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_paper(url: &str, arxiv_id: Option<&str>) -> NewPaperFull {
+        NewPaperFull {
+            paper: NewPaper {
+                url: url.to_string(),
+                arxiv_id: arxiv_id.map(|id| id.parse().unwrap()),
+                source: "arxiv".to_string(),
+                status: PaperStatus::Active,
+                title: String::new(),
+                body: String::new(),
+                description: String::new(),
+            },
+            authors: Vec::new(),
+            subjects: Vec::new(),
+            captions: Vec::new(),
         }
+    }
+
+    fn names(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
 
-        for subject in subjects {
-            let subject_id = self.insert_subject(subject, &mut tx).await?;
-            self.set_paper_subject(paper_id, subject_id, &mut tx).await?;
+    fn change(field: &str, old_value: Option<&str>, new_value: Option<&str>) -> PaperChange {
+        PaperChange {
+            field: field.to_string(),
+            old_value: old_value.map(str::to_string),
+            new_value: new_value.map(str::to_string),
         }
+    }
+
+    #[test]
+    fn short_body_is_not_compressed() {
+        let (compression, size, content) = encode_body("Short body");
+        assert_eq!(compression, BodyCompression::None);
+        assert_eq!(size, 10);
+        assert_eq!(content, b"Short body");
+        assert_eq!(
+            decode_body(1, compression, size, content).unwrap(),
+            "Short body"
+        );
+    }
 
-        Ok(tx.commit().await?)
+    #[test]
+    fn long_body_round_trips() {
+        let body = "Deep nets are deep. ".repeat(BODY_COMPRESSION_MIN_SIZE);
+        let (compression, size, content) = encode_body(&body);
+        assert_eq!(compression, BodyCompression::Zstd);
+        assert_eq!(size as usize, body.len());
+        assert!(content.len() < body.len());
+        assert_eq!(decode_body(1, compression, size, content).unwrap(), body);
+    }
+
+    #[test]
+    fn corrupt_body_fails_to_decode() {
+        assert!(matches!(
+            decode_body(7, BodyCompression::Zstd, 100, b"not zstd".to_vec()),
+            Err(Error::Body(7, _))
+        ));
+        assert!(matches!(
+            decode_body(7, BodyCompression::None, 2, vec![0xff, 0xfe]),
+            Err(Error::Body(7, _))
+        ));
+    }
+
+    #[test]
+    fn duplicates_point_to_lowest_id() {
+        let paper = |id, url: &str| PaperRef {
+            id,
+            url: url.to_string(),
+        };
+        let issues = find_duplicates(vec![
+            paper(3, "https://export.arxiv.org/abs/2101.00001v2"),
+            paper(1, "https://arxiv.org/abs/2101.00001"),
+            paper(2, "https://arxiv.org/abs/2101.00002"),
+            paper(5, "https://example.org/paper"),
+            paper(4, "https://example.org/paper"),
+        ]);
+        assert_eq!(
+            issues,
+            [
+                IntegrityIssue::DuplicatePaper {
+                    paper_id: 3,
+                    url: "https://export.arxiv.org/abs/2101.00001v2".to_string(),
+                    original_id: 1,
+                },
+                IntegrityIssue::DuplicatePaper {
+                    paper_id: 5,
+                    url: "https://example.org/paper".to_string(),
+                    original_id: 4,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn dedup_keeps_first_of_url_or_arxiv_id() {
+        let mut papers = vec![
+            new_paper("https://arxiv.org/abs/2101.00001", Some("2101.00001")),
+            new_paper("https://arxiv.org/abs/2101.00002", Some("2101.00002")),
+            new_paper(
+                "https://export.arxiv.org/abs/2101.00001",
+                Some("2101.00001"),
+            ),
+            new_paper("https://arxiv.org/abs/2101.00002", Some("2101.00003")),
+            new_paper("https://example.org/a", None),
+            new_paper("https://example.org/b", None),
+            new_paper("https://example.org/a", None),
+        ];
+        dedup_papers(&mut papers);
+        assert_eq!(
+            papers
+                .iter()
+                .map(|p| p.paper.url.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "https://arxiv.org/abs/2101.00001",
+                "https://arxiv.org/abs/2101.00002",
+                "https://example.org/a",
+                "https://example.org/b",
+            ]
+        );
+    }
+
+    #[test]
+    fn unchanged_metadata_has_no_diff() {
+        let diff = diff_metadata(
+            StoredMetadata {
+                title: "Title".to_string(),
+                description: "Abstract".to_string(),
+                status: PaperStatus::Active,
+                authors: names(&["Ada", "Bob"]),
+                subjects: names(&["cs.LG"]),
+            },
+            &PaperMetadataUpdate {
+                title: "Title".to_string(),
+                description: "Abstract".to_string(),
+                status: PaperStatus::Active,
+                authors: vec![
+                    NewAuthor {
+                        name: "Bob".to_string(),
+                    },
+                    NewAuthor {
+                        name: "Ada".to_string(),
+                    },
+                ],
+                subjects: vec![NewSubject {
+                    name: "cs.LG".to_string(),
+                }],
+            },
+        );
+        assert!(diff.changes.is_empty());
+        assert!(!diff.paper_changed);
+    }
+
+    #[test]
+    fn metadata_diff_lists_every_change() {
+        let diff = diff_metadata(
+            StoredMetadata {
+                title: "Title".to_string(),
+                description: "Abstract".to_string(),
+                status: PaperStatus::Active,
+                authors: names(&["Ada", "Bob"]),
+                subjects: names(&["cs.LG"]),
+            },
+            &PaperMetadataUpdate {
+                title: "New title".to_string(),
+                description: "Abstract".to_string(),
+                status: PaperStatus::Withdrawn,
+                authors: vec![NewAuthor {
+                    name: "Ada".to_string(),
+                }],
+                subjects: vec![
+                    NewSubject {
+                        name: "cs.LG".to_string(),
+                    },
+                    NewSubject {
+                        name: "cs.CL".to_string(),
+                    },
+                ],
+            },
+        );
+        assert!(diff.paper_changed);
+        assert_eq!(
+            diff.changes,
+            [
+                change("title", Some("Title"), Some("New title")),
+                change("status", Some("active"), Some("withdrawn")),
+                change("author", Some("Bob"), None),
+                change("subject", None, Some("cs.CL")),
+            ]
+        );
+        assert!(diff.added_authors.is_empty());
+        assert_eq!(diff.removed_authors, ["Bob"]);
+        assert_eq!(diff.added_subjects, ["cs.CL"]);
+        assert!(diff.removed_subjects.is_empty());
+    }
+
+    #[test]
+    fn link_changes_leave_paper_unchanged() {
+        let diff = diff_metadata(
+            StoredMetadata {
+                title: "Title".to_string(),
+                description: "Abstract".to_string(),
+                status: PaperStatus::Active,
+                authors: names(&[]),
+                subjects: names(&["cs.LG"]),
+            },
+            &PaperMetadataUpdate {
+                title: "Title".to_string(),
+                description: "Abstract".to_string(),
+                status: PaperStatus::Active,
+                authors: vec![NewAuthor {
+                    name: "Ada".to_string(),
+                }],
+                subjects: Vec::new(),
+            },
+        );
+        assert!(!diff.paper_changed);
+        assert_eq!(
+            diff.changes,
+            [
+                change("author", None, Some("Ada")),
+                change("subject", Some("cs.LG"), None),
+            ]
+        );
     }
 }
@@ -0,0 +1,1102 @@
+use futures::stream::{BoxStream, StreamExt};
+use sqlx::postgres::{PgListener, PgPoolOptions};
+use sqlx::types::Json;
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    decode_body, dedup_papers, diff_metadata, encode_body, find_duplicates, with_bodies, Error,
+    MetadataDiff, PaperStore, PoolConfig, Result, StoredMetadata,
+};
+use crate::arxiv_id::ArxivId;
+use crate::models::{
+    self, BodyCompression, CaptionKind, IntegrityIssue, NewPaperFull, NewScrapeRun, PaperChange,
+    PaperFilter, PaperMetadataUpdate, PaperRef, PaperStatus,
+};
+
+/// Schema migrations from `shared/migrations/postgres`, embedded at compile time
+static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// Channel notified by triggers on every change of a paper
+const CHANGES_CHANNEL: &str = "papers_changed";
+
+#[derive(Clone)]
+pub struct PostgresStore {
+    pool: sqlx::Pool<sqlx::Postgres>,
+}
+
+impl PostgresStore {
+    pub async fn new(db_url: &str, pool: &PoolConfig) -> Result<PostgresStore> {
+        Ok(PostgresStore {
+            pool: PgPoolOptions::new()
+                .max_connections(pool.max_connections)
+                .min_connections(pool.min_connections)
+                .acquire_timeout(pool.acquire_timeout)
+                .idle_timeout(pool.idle_timeout)
+                .connect(db_url)
+                .await?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl PaperStore for PostgresStore {
+    async fn migrate(&self) -> Result<()> {
+        log::debug!("DB: applying migrations");
+        MIGRATOR.run(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn get_all_papers(&self) -> Result<Vec<models::PaperSummary>> {
+        sqlx::query_as!(
+            models::PaperSummary,
+            r#"SELECT id, url, arxiv_id AS "arxiv_id: ArxivId", source, title, description,
+                    fetched_at, status AS "status: PaperStatus", created_at, updated_at,
+                    change_seq
+                FROM papers"#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn get_papers_page(
+        &self,
+        after_id: Option<models::Id>,
+        limit: i64,
+    ) -> Result<Vec<models::Paper>> {
+        let papers = sqlx::query_as!(
+            models::PaperSummary,
+            r#"SELECT id, url, arxiv_id AS "arxiv_id: ArxivId", source, title, description,
+                    fetched_at, status AS "status: PaperStatus", created_at, updated_at,
+                    change_seq
+                FROM papers
+                WHERE ($1::int4 IS NULL OR id > $1)
+                ORDER BY id
+                LIMIT $2"#,
+            after_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let paper_ids = papers.iter().map(|p| p.id).collect::<Vec<_>>();
+        let bodies = self.get_paper_bodies(&paper_ids).await?;
+
+        Ok(with_bodies(papers, bodies))
+    }
+
+    async fn count_papers(&self) -> Result<i64> {
+        sqlx::query_scalar!("SELECT COUNT(*) FROM papers")
+            .fetch_one(&self.pool)
+            .await
+            .map(|r| r.unwrap()) // TODO: not pretty
+            .map_err(|e| e.into())
+    }
+
+    async fn get_paper(&self, desired_id: i32) -> Result<Option<models::Paper>> {
+        let paper = sqlx::query_as!(
+            models::PaperSummary,
+            r#"SELECT id, url, arxiv_id AS "arxiv_id: ArxivId", source, title, description,
+                    fetched_at, status AS "status: PaperStatus", created_at, updated_at,
+                    change_seq
+                FROM papers WHERE id = $1"#,
+            desired_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(paper) = paper else {
+            return Ok(None);
+        };
+
+        let body = self
+            .get_paper_bodies(&[desired_id])
+            .await?
+            .remove(&desired_id)
+            .unwrap_or_default();
+
+        Ok(Some(paper.with_body(body)))
+    }
+
+    async fn get_paper_bodies(
+        &self,
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, String>> {
+        sqlx::query!(
+            r#"SELECT paper_id, compression AS "compression: BodyCompression", size, content
+                FROM paper_bodies WHERE paper_id = ANY($1)"#,
+            paper_ids
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|r| {
+            let body = decode_body(r.paper_id, r.compression, r.size, r.content)?;
+            Ok((r.paper_id, body))
+        })
+        .collect()
+    }
+
+    async fn get_paper_authors(&self, desired_paper_id: i32) -> Result<Vec<models::Author>> {
+        sqlx::query_as!(
+            models::Author,
+            "SELECT authors.id, authors.name
+                FROM authors
+            JOIN paper_author ON authors.id = paper_author.author_id
+                WHERE paper_author.paper_id = $1",
+            desired_paper_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn get_paper_captions(&self, paper_id: i32) -> Result<Vec<models::Caption>> {
+        sqlx::query_as!(
+            models::Caption,
+            r#"SELECT id, paper_id, kind AS "kind: CaptionKind", number, text
+                FROM paper_captions
+                WHERE paper_id = $1
+                ORDER BY id"#,
+            paper_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn get_papers_by_ids(
+        &self,
+        ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, models::PaperSummary>> {
+        Ok(sqlx::query_as!(
+            models::PaperSummary,
+            r#"SELECT id, url, arxiv_id AS "arxiv_id: ArxivId", source, title, description,
+                    fetched_at, status AS "status: PaperStatus", created_at, updated_at,
+                    change_seq
+                FROM papers WHERE id = ANY($1)"#,
+            ids
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|paper| (paper.id, paper))
+        .collect())
+    }
+
+    async fn get_papers_full(
+        &self,
+        ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, models::PaperFull>> {
+        Ok(sqlx::query!(
+            r#"SELECT id, url, arxiv_id AS "arxiv_id: ArxivId", source, title, description,
+                    fetched_at, status AS "status: PaperStatus", created_at, updated_at,
+                    change_seq,
+                    (SELECT json_agg(json_build_object('id', authors.id, 'name', authors.name)
+                            ORDER BY authors.id)
+                        FROM authors
+                    JOIN paper_author ON authors.id = paper_author.author_id
+                        WHERE paper_author.paper_id = papers.id
+                    ) AS "authors: Json<Vec<models::Author>>",
+                    (SELECT json_agg(json_build_object('id', subjects.id, 'name', subjects.name)
+                            ORDER BY subjects.id)
+                        FROM subjects
+                    JOIN paper_subject ON subjects.id = paper_subject.subject_id
+                        WHERE paper_subject.paper_id = papers.id
+                    ) AS "subjects: Json<Vec<models::Subject>>"
+                FROM papers WHERE id = ANY($1)"#,
+            ids
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|r| {
+            let paper = models::PaperSummary {
+                id: r.id,
+                url: r.url,
+                arxiv_id: r.arxiv_id,
+                source: r.source,
+                title: r.title,
+                description: r.description,
+                fetched_at: r.fetched_at,
+                status: r.status,
+                created_at: r.created_at,
+                updated_at: r.updated_at,
+                change_seq: r.change_seq,
+            };
+            let full = models::PaperFull {
+                paper,
+                authors: r.authors.map(|a| a.0).unwrap_or_default(),
+                subjects: r.subjects.map(|s| s.0).unwrap_or_default(),
+            };
+            (r.id, full)
+        })
+        .collect())
+    }
+
+    async fn get_authors_for_papers(
+        &self,
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, Vec<models::Author>>> {
+        let mut authors = HashMap::<_, Vec<_>>::new();
+        for r in sqlx::query!(
+            "SELECT paper_author.paper_id, authors.id, authors.name
+                FROM authors
+            JOIN paper_author ON authors.id = paper_author.author_id
+                WHERE paper_author.paper_id = ANY($1)
+                ORDER BY authors.id",
+            paper_ids
+        )
+        .fetch_all(&self.pool)
+        .await?
+        {
+            authors.entry(r.paper_id).or_default().push(models::Author {
+                id: r.id,
+                name: r.name,
+            });
+        }
+
+        Ok(authors)
+    }
+
+    async fn get_subjects_for_papers(
+        &self,
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, Vec<models::Subject>>> {
+        let mut subjects = HashMap::<_, Vec<_>>::new();
+        for r in sqlx::query!(
+            "SELECT paper_subject.paper_id, subjects.id, subjects.name
+                FROM subjects
+            JOIN paper_subject ON subjects.id = paper_subject.subject_id
+                WHERE paper_subject.paper_id = ANY($1)
+                ORDER BY subjects.id",
+            paper_ids
+        )
+        .fetch_all(&self.pool)
+        .await?
+        {
+            subjects
+                .entry(r.paper_id)
+                .or_default()
+                .push(models::Subject {
+                    id: r.id,
+                    name: r.name,
+                });
+        }
+
+        Ok(subjects)
+    }
+
+    async fn get_captions_for_papers(
+        &self,
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, Vec<models::Caption>>> {
+        let mut captions = HashMap::<_, Vec<_>>::new();
+        for caption in sqlx::query_as!(
+            models::Caption,
+            r#"SELECT id, paper_id, kind AS "kind: CaptionKind", number, text
+                FROM paper_captions
+                WHERE paper_id = ANY($1)
+                ORDER BY id"#,
+            paper_ids
+        )
+        .fetch_all(&self.pool)
+        .await?
+        {
+            captions.entry(caption.paper_id).or_default().push(caption);
+        }
+
+        Ok(captions)
+    }
+
+    async fn paper_exists(&self, desired_url: &str) -> Result<bool> {
+        sqlx::query_scalar!(
+            "SELECT EXISTS(SELECT * FROM papers WHERE url = $1)
+                OR EXISTS(SELECT * FROM takedowns WHERE url = $1)",
+            desired_url
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map(|r| r.unwrap()) // TODO: not pretty
+        .map_err(|e| e.into())
+    }
+
+    async fn insert_papers_full(&self, mut papers: Vec<NewPaperFull>) -> Result<HashSet<String>> {
+        dedup_papers(&mut papers);
+        if papers.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        log::trace!("DB: inserting batch of {} papers", papers.len());
+
+        let mut tx = self.pool.begin().await?;
+
+        let (mut urls, mut arxiv_ids) = (Vec::new(), Vec::new());
+        let (mut sources, mut statuses) = (Vec::new(), Vec::new());
+        let (mut titles, mut descriptions, mut bodies) = (Vec::new(), Vec::new(), Vec::new());
+        for NewPaperFull { paper, .. } in &papers {
+            urls.push(paper.url.clone());
+            arxiv_ids.push(paper.arxiv_id.as_ref().map(|id| id.to_string()));
+            sources.push(paper.source.clone());
+            statuses.push(paper.status);
+            titles.push(paper.title.clone());
+            descriptions.push(paper.description.clone());
+            bodies.push(paper.body.clone());
+        }
+
+        let paper_ids = sqlx::query!(
+            "INSERT INTO papers (url, arxiv_id, source, status, title, description, search_vector)
+                SELECT url, arxiv_id, source, status, title, description,
+                        setweight(to_tsvector('english', left(body, 300000)), 'D')
+                    FROM UNNEST($1::varchar[], $2::varchar[], $3::varchar[],
+                        $4::paper_status[], $5::varchar[], $6::text[], $7::text[])
+                        AS new (url, arxiv_id, source, status, title, description, body)
+                    WHERE NOT EXISTS(SELECT * FROM takedowns
+                        WHERE takedowns.url = new.url OR takedowns.arxiv_id = new.arxiv_id)
+                ON CONFLICT DO NOTHING
+                RETURNING id, url",
+            &urls,
+            &arxiv_ids as &[Option<String>],
+            &sources,
+            &statuses as &[PaperStatus],
+            &titles,
+            &descriptions,
+            &bodies,
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|r| (r.url, r.id))
+        .collect::<HashMap<_, _>>();
+
+        let author_names = papers
+            .iter()
+            .flat_map(|p| p.authors.iter().map(|a| a.name.clone()))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        sqlx::query!(
+            "INSERT INTO authors (name)
+                SELECT * FROM UNNEST($1::varchar[])
+                ON CONFLICT(name) DO NOTHING",
+            &author_names,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let author_ids = sqlx::query!(
+            "SELECT id, name FROM authors WHERE name = ANY($1)",
+            &author_names,
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|r| (r.name, r.id))
+        .collect::<HashMap<_, _>>();
+
+        let subject_names = papers
+            .iter()
+            .flat_map(|p| p.subjects.iter().map(|s| s.name.clone()))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        sqlx::query!(
+            "INSERT INTO subjects (name)
+                SELECT * FROM UNNEST($1::varchar[])
+                ON CONFLICT(name) DO NOTHING",
+            &subject_names,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let subject_ids = sqlx::query!(
+            "SELECT id, name FROM subjects WHERE name = ANY($1)",
+            &subject_names,
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|r| (r.name, r.id))
+        .collect::<HashMap<_, _>>();
+
+        let (mut pa_paper_ids, mut pa_author_ids) = (Vec::new(), Vec::new());
+        let (mut ps_paper_ids, mut ps_subject_ids) = (Vec::new(), Vec::new());
+        let (mut caption_paper_ids, mut caption_kinds) = (Vec::new(), Vec::new());
+        let (mut caption_numbers, mut caption_texts) = (Vec::new(), Vec::new());
+        let (mut body_paper_ids, mut body_compressions) = (Vec::new(), Vec::new());
+        let (mut body_sizes, mut body_contents) = (Vec::new(), Vec::new());
+        for NewPaperFull {
+            paper,
+            authors,
+            subjects,
+            captions,
+        } in &papers
+        {
+            let Some(&paper_id) = paper_ids.get(&paper.url) else {
+                log::debug!("DB: paper {:?} already exists, skipping", paper.url);
+                continue;
+            };
+            let (compression, size, content) = encode_body(&paper.body);
+            body_paper_ids.push(paper_id);
+            body_compressions.push(compression);
+            body_sizes.push(size);
+            body_contents.push(content);
+            for author in authors {
+                pa_paper_ids.push(paper_id);
+                pa_author_ids.push(author_ids[&author.name]);
+            }
+            for subject in subjects {
+                ps_paper_ids.push(paper_id);
+                ps_subject_ids.push(subject_ids[&subject.name]);
+            }
+            for caption in captions {
+                caption_paper_ids.push(paper_id);
+                caption_kinds.push(caption.kind);
+                caption_numbers.push(caption.number.clone());
+                caption_texts.push(caption.text.clone());
+            }
+        }
+
+        sqlx::query!(
+            "INSERT INTO paper_bodies (paper_id, compression, size, content)
+                SELECT * FROM UNNEST($1::int4[], $2::body_compression[], $3::int4[], $4::bytea[])",
+            &body_paper_ids,
+            &body_compressions as &[BodyCompression],
+            &body_sizes,
+            &body_contents,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO paper_author (paper_id, author_id)
+                SELECT * FROM UNNEST($1::int4[], $2::int4[])
+                ON CONFLICT DO NOTHING",
+            &pa_paper_ids,
+            &pa_author_ids,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO paper_subject (paper_id, subject_id)
+                SELECT * FROM UNNEST($1::int4[], $2::int4[])
+                ON CONFLICT DO NOTHING",
+            &ps_paper_ids,
+            &ps_subject_ids,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO paper_captions (paper_id, kind, number, text)
+                SELECT * FROM UNNEST($1::int4[], $2::caption_kind[], $3::varchar[], $4::text[])",
+            &caption_paper_ids,
+            &caption_kinds as &[CaptionKind],
+            &caption_numbers,
+            &caption_texts,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(paper_ids.into_keys().collect())
+    }
+
+    async fn insert_scrape_run(&self, run: NewScrapeRun) -> Result<models::Id> {
+        log::trace!("DB: inserting scrape run started at {:?}", run.started_at);
+        Ok(sqlx::query_scalar!(
+            "INSERT INTO scrape_runs (started_at, finished_at, query, pages_scraped,
+                    papers_found, papers_stored, papers_failed, bytes_downloaded, failures)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                RETURNING id",
+            run.started_at,
+            run.finished_at,
+            run.query,
+            run.pages_scraped,
+            run.papers_found,
+            run.papers_stored,
+            run.papers_failed,
+            run.bytes_downloaded,
+            sqlx::types::Json(&run.failures) as _,
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    async fn select_papers(&self, filter: &PaperFilter) -> Result<Vec<PaperRef>> {
+        sqlx::query_as!(
+            PaperRef,
+            "SELECT papers.id, papers.url FROM papers
+                WHERE ($1::int4[] IS NULL OR papers.id = ANY($1))
+                    AND ($2::timestamptz IS NULL OR papers.fetched_at < $2)
+                    AND ($3::varchar IS NULL OR EXISTS(
+                        SELECT * FROM paper_subject
+                        JOIN subjects ON subjects.id = paper_subject.subject_id
+                        WHERE paper_subject.paper_id = papers.id AND subjects.name = $3))
+                ORDER BY papers.fetched_at
+                LIMIT $4",
+            filter.ids.as_deref(),
+            filter.fetched_before,
+            filter.subject,
+            filter.limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn get_embeddings(
+        &self,
+        model: &str,
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, models::PaperEmbedding>> {
+        Ok(sqlx::query_as!(
+            models::PaperEmbedding,
+            r#"SELECT paper_id, model, content_hash, embedding::real[] AS "embedding!"
+                FROM paper_embeddings
+                WHERE model = $1 AND paper_id = ANY($2)"#,
+            model,
+            paper_ids,
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|e| (e.paper_id, e))
+        .collect())
+    }
+
+    async fn upsert_embeddings(&self, embeddings: Vec<models::PaperEmbedding>) -> Result<()> {
+        log::trace!("DB: storing {} embeddings", embeddings.len());
+
+        // NOTE: a row can not be upserted twice by one statement, the last
+        // embedding of a paper and model wins
+        let mut seen = HashSet::new();
+        let mut embeddings = embeddings
+            .into_iter()
+            .rev()
+            .filter(|e| seen.insert((e.paper_id, e.model.clone())))
+            .collect::<Vec<_>>();
+        embeddings.reverse();
+
+        let paper_ids = embeddings.iter().map(|e| e.paper_id).collect::<Vec<_>>();
+        let models = embeddings
+            .iter()
+            .map(|e| e.model.clone())
+            .collect::<Vec<_>>();
+        let dimensions = embeddings
+            .iter()
+            .map(|e| e.embedding.len() as i32)
+            .collect::<Vec<_>>();
+        let content_hashes = embeddings
+            .iter()
+            .map(|e| e.content_hash.clone())
+            .collect::<Vec<_>>();
+        // NOTE: arrays of arrays can not be unnested row by row, embeddings
+        // are passed in text form of `vector` instead
+        let vectors = embeddings
+            .iter()
+            .map(|e| vector_literal(&e.embedding))
+            .collect::<Vec<_>>();
+
+        sqlx::query!(
+            "INSERT INTO paper_embeddings (paper_id, model, dimension, content_hash, embedding)
+                SELECT paper_id, model, dimension, content_hash, embedding::vector
+                    FROM UNNEST($1::int4[], $2::varchar[], $3::int4[], $4::varchar[], $5::text[])
+                        AS new (paper_id, model, dimension, content_hash, embedding)
+                ON CONFLICT (paper_id, model) DO UPDATE
+                SET dimension = EXCLUDED.dimension,
+                    content_hash = EXCLUDED.content_hash,
+                    embedding = EXCLUDED.embedding",
+            &paper_ids,
+            &models,
+            &dimensions,
+            &content_hashes,
+            &vectors,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn nearest_papers(
+        &self,
+        model: &str,
+        embedding: &[f32],
+        limit: i64,
+    ) -> Result<Vec<models::SearchHit>> {
+        sqlx::query_as!(
+            models::SearchHit,
+            r#"SELECT paper_id, (1 - (embedding <=> $2::real[]::vector))::real AS "score!"
+                FROM paper_embeddings
+                WHERE model = $1 AND dimension = cardinality($2::real[])
+                ORDER BY embedding <=> $2::real[]::vector
+                LIMIT $3"#,
+            model,
+            embedding,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn changes_since(&self, seq: i64, limit: i64) -> Result<Vec<models::ChangeEvent>> {
+        sqlx::query_as!(
+            models::ChangeEvent,
+            r#"SELECT paper_id AS "paper_id!", change_seq AS "change_seq!",
+                    updated_at AS "updated_at!", deleted AS "deleted!"
+                FROM (
+                    SELECT id AS paper_id, change_seq, updated_at, false AS deleted
+                        FROM papers WHERE change_seq > $1
+                    UNION ALL
+                    SELECT paper_id, change_seq, taken_down_at, true
+                        FROM takedowns WHERE change_seq > $1
+                    UNION ALL
+                    SELECT paper_id, change_seq, deleted_at, true
+                        FROM paper_deletions WHERE change_seq > $1
+                ) changes
+                ORDER BY change_seq
+                LIMIT $2"#,
+            seq,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn recent_changes(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+        seq: i64,
+    ) -> Result<Vec<models::ChangeEvent>> {
+        sqlx::query_as!(
+            models::ChangeEvent,
+            r#"SELECT paper_id AS "paper_id!", change_seq AS "change_seq!",
+                    updated_at AS "updated_at!", deleted AS "deleted!"
+                FROM (
+                    SELECT id AS paper_id, change_seq, updated_at, false AS deleted
+                        FROM papers WHERE updated_at >= $1 AND change_seq <= $2
+                    UNION ALL
+                    SELECT paper_id, change_seq, taken_down_at, true
+                        FROM takedowns WHERE taken_down_at >= $1 AND change_seq <= $2
+                    UNION ALL
+                    SELECT paper_id, change_seq, deleted_at, true
+                        FROM paper_deletions WHERE deleted_at >= $1 AND change_seq <= $2
+                ) changes
+                ORDER BY change_seq"#,
+            since,
+            seq,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn subscribe_changes(&self) -> Result<BoxStream<'static, Result<models::ChangeEvent>>> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(CHANGES_CHANNEL).await?;
+
+        Ok(listener
+            .into_stream()
+            .map(|notification| {
+                let payload = notification?.payload().to_string();
+                serde_json::from_str(&payload).map_err(|_| Error::Notification(payload))
+            })
+            .boxed())
+    }
+
+    async fn take_down_paper(
+        &self,
+        paper_id: models::Id,
+        reason: &str,
+    ) -> Result<Option<models::Takedown>> {
+        log::info!("DB: taking down paper {paper_id:?}: {reason}");
+        sqlx::query_as!(
+            models::Takedown,
+            r#"WITH deleted AS (DELETE FROM papers WHERE id = $1 RETURNING id, url, arxiv_id)
+                INSERT INTO takedowns (paper_id, url, arxiv_id, reason)
+                SELECT id, url, arxiv_id, $2 FROM deleted
+                RETURNING id, paper_id, url, arxiv_id AS "arxiv_id: ArxivId", reason,
+                    taken_down_at, change_seq"#,
+            paper_id,
+            reason,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn get_takedowns(&self) -> Result<Vec<models::Takedown>> {
+        sqlx::query_as!(
+            models::Takedown,
+            r#"SELECT id, paper_id, url, arxiv_id AS "arxiv_id: ArxivId", reason, taken_down_at,
+                    change_seq
+                FROM takedowns ORDER BY id"#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn insert_takedowns(&self, takedowns: Vec<models::NewTakedown>) -> Result<u64> {
+        log::trace!("DB: inserting {} takedowns", takedowns.len());
+
+        let (mut urls, mut arxiv_ids) = (Vec::new(), Vec::new());
+        let (mut reasons, mut taken_down_ats) = (Vec::new(), Vec::new());
+        for takedown in takedowns {
+            urls.push(takedown.url);
+            arxiv_ids.push(takedown.arxiv_id.map(|id| id.to_string()));
+            reasons.push(takedown.reason);
+            taken_down_ats.push(takedown.taken_down_at);
+        }
+
+        Ok(sqlx::query!(
+            "INSERT INTO takedowns (paper_id, url, arxiv_id, reason, taken_down_at)
+                SELECT 0, * FROM UNNEST($1::varchar[], $2::varchar[], $3::text[],
+                    $4::timestamptz[])
+                ON CONFLICT DO NOTHING",
+            &urls,
+            &arxiv_ids as &[Option<String>],
+            &reasons,
+            &taken_down_ats,
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected())
+    }
+
+    async fn delete_papers(&self, paper_ids: &[models::Id]) -> Result<u64> {
+        log::info!("DB: deleting papers {paper_ids:?}");
+        Ok(sqlx::query!(
+            "WITH deleted AS (DELETE FROM papers WHERE id = ANY($1) RETURNING id)
+                INSERT INTO paper_deletions (paper_id) SELECT id FROM deleted",
+            paper_ids
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected())
+    }
+
+    async fn check_integrity(&self, embedding_model: Option<&str>) -> Result<Vec<IntegrityIssue>> {
+        let mut issues = Vec::new();
+
+        issues.extend(
+            sqlx::query!(
+                "SELECT id, name FROM authors
+                    WHERE NOT EXISTS(SELECT * FROM paper_author WHERE author_id = authors.id)"
+            )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|r| IntegrityIssue::OrphanAuthor {
+                id: r.id,
+                name: r.name,
+            }),
+        );
+
+        issues.extend(
+            sqlx::query!(
+                "SELECT id, name FROM subjects
+                    WHERE NOT EXISTS(SELECT * FROM paper_subject WHERE subject_id = subjects.id)"
+            )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|r| IntegrityIssue::OrphanSubject {
+                id: r.id,
+                name: r.name,
+            }),
+        );
+
+        issues.extend(
+            sqlx::query!("SELECT id, url FROM papers WHERE btrim(title) = ''")
+                .fetch_all(&self.pool)
+                .await?
+                .into_iter()
+                .map(|r| IntegrityIssue::EmptyTitle {
+                    paper_id: r.id,
+                    url: r.url,
+                }),
+        );
+
+        issues.extend(
+            sqlx::query!(
+                "SELECT id, url FROM papers
+                    WHERE NOT EXISTS(SELECT * FROM paper_bodies
+                        WHERE paper_id = papers.id AND size > 0)"
+            )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|r| IntegrityIssue::EmptyBody {
+                paper_id: r.id,
+                url: r.url,
+            }),
+        );
+
+        issues.extend(find_duplicates(
+            sqlx::query_as!(PaperRef, "SELECT id, url FROM papers")
+                .fetch_all(&self.pool)
+                .await?,
+        ));
+
+        if let Some(model) = embedding_model {
+            issues.extend(
+                sqlx::query!(
+                    "SELECT paper_id, model FROM paper_embeddings WHERE model <> $1",
+                    model
+                )
+                .fetch_all(&self.pool)
+                .await?
+                .into_iter()
+                .map(|r| IntegrityIssue::StaleEmbedding {
+                    paper_id: r.paper_id,
+                    model: r.model,
+                }),
+            );
+        }
+
+        Ok(issues)
+    }
+
+    async fn delete_orphans(&self) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+
+        let authors = sqlx::query!(
+            "DELETE FROM authors
+                WHERE NOT EXISTS(SELECT * FROM paper_author WHERE author_id = authors.id)"
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        let subjects = sqlx::query!(
+            "DELETE FROM subjects
+                WHERE NOT EXISTS(SELECT * FROM paper_subject WHERE subject_id = subjects.id)"
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        tx.commit().await?;
+
+        Ok(authors + subjects)
+    }
+
+    async fn delete_stale_embeddings(&self, model: &str) -> Result<u64> {
+        Ok(
+            sqlx::query!("DELETE FROM paper_embeddings WHERE model <> $1", model)
+                .execute(&self.pool)
+                .await?
+                .rows_affected(),
+        )
+    }
+
+    async fn search_papers(
+        &self,
+        query: &str,
+        limit: i64,
+        include_withdrawn: bool,
+    ) -> Result<Vec<models::SearchHit>> {
+        sqlx::query_as!(
+            models::SearchHit,
+            r#"SELECT papers.id AS paper_id, ts_rank_cd(papers.search_vector, query) AS "score!"
+                FROM papers, websearch_to_tsquery('english', $1) query
+                WHERE papers.search_vector @@ query
+                    AND ($3 OR papers.status <> 'withdrawn')
+                ORDER BY 2 DESC
+                LIMIT $2"#,
+            query,
+            limit,
+            include_withdrawn,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn update_paper_metadata(
+        &self,
+        paper_id: models::Id,
+        update: PaperMetadataUpdate,
+    ) -> Result<Vec<PaperChange>> {
+        log::trace!("DB: updating metadata of paper {paper_id:?}");
+
+        let mut tx = self.pool.begin().await?;
+
+        let current = sqlx::query!(
+            r#"SELECT title, description, status AS "status: PaperStatus"
+                FROM papers WHERE id = $1 FOR UPDATE"#,
+            paper_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let authors = sqlx::query_scalar!(
+            "SELECT authors.name FROM authors
+            JOIN paper_author ON authors.id = paper_author.author_id
+                WHERE paper_author.paper_id = $1",
+            paper_id
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .collect();
+
+        let subjects = sqlx::query_scalar!(
+            "SELECT subjects.name FROM subjects
+            JOIN paper_subject ON subjects.id = paper_subject.subject_id
+                WHERE paper_subject.paper_id = $1",
+            paper_id
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .collect();
+
+        let MetadataDiff {
+            changes,
+            paper_changed,
+            added_authors,
+            removed_authors,
+            added_subjects,
+            removed_subjects,
+        } = diff_metadata(
+            StoredMetadata {
+                title: current.title,
+                description: current.description,
+                status: current.status,
+                authors,
+                subjects,
+            },
+            &update,
+        );
+
+        if paper_changed {
+            sqlx::query!(
+                "UPDATE papers SET title = $2, description = $3, status = $4, fetched_at = now()
+                    WHERE id = $1",
+                paper_id,
+                update.title,
+                update.description,
+                update.status as _,
+            )
+            .execute(&mut *tx)
+            .await?;
+        } else {
+            sqlx::query!(
+                "UPDATE papers SET fetched_at = now() WHERE id = $1",
+                paper_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        if changes.is_empty() {
+            tx.commit().await?;
+            return Ok(changes);
+        }
+
+        sqlx::query!(
+            "INSERT INTO authors (name)
+                SELECT * FROM UNNEST($1::varchar[])
+                ON CONFLICT(name) DO NOTHING",
+            &added_authors,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO paper_author (paper_id, author_id)
+                SELECT $1, authors.id FROM authors WHERE name = ANY($2)
+                ON CONFLICT DO NOTHING",
+            paper_id,
+            &added_authors,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "DELETE FROM paper_author
+                USING authors
+                WHERE paper_author.author_id = authors.id
+                    AND paper_author.paper_id = $1
+                    AND authors.name = ANY($2)",
+            paper_id,
+            &removed_authors,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO subjects (name)
+                SELECT * FROM UNNEST($1::varchar[])
+                ON CONFLICT(name) DO NOTHING",
+            &added_subjects,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO paper_subject (paper_id, subject_id)
+                SELECT $1, subjects.id FROM subjects WHERE name = ANY($2)
+                ON CONFLICT DO NOTHING",
+            paper_id,
+            &added_subjects,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "DELETE FROM paper_subject
+                USING subjects
+                WHERE paper_subject.subject_id = subjects.id
+                    AND paper_subject.paper_id = $1
+                    AND subjects.name = ANY($2)",
+            paper_id,
+            &removed_subjects,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let fields = changes.iter().map(|c| c.field.clone()).collect::<Vec<_>>();
+        let old_values = changes
+            .iter()
+            .map(|c| c.old_value.clone())
+            .collect::<Vec<_>>();
+        let new_values = changes
+            .iter()
+            .map(|c| c.new_value.clone())
+            .collect::<Vec<_>>();
+
+        sqlx::query!(
+            "INSERT INTO paper_changes (paper_id, field, old_value, new_value)
+                SELECT $1, * FROM UNNEST($2::varchar[], $3::text[], $4::text[])",
+            paper_id,
+            &fields,
+            &old_values as _,
+            &new_values as _,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(changes)
+    }
+}
+
+/// Text form of a pgvector `vector`, e.g. `[0.5,-1,2]`
+fn vector_literal(embedding: &[f32]) -> String {
+    let values = embedding
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("[{values}]")
+}
@@ -0,0 +1,1421 @@
+use futures::stream::BoxStream;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use super::{
+    decode_body, dedup_papers, diff_metadata, encode_body, find_duplicates, with_bodies, Error,
+    MetadataDiff, PaperStore, PoolConfig, Result, StoredMetadata,
+};
+use crate::arxiv_id::ArxivId;
+use crate::models::{
+    self, BodyCompression, IntegrityIssue, NewPaperFull, NewScrapeRun, PaperChange, PaperFilter,
+    PaperMetadataUpdate, PaperRef, PaperStatus,
+};
+
+/// Schema migrations from `shared/migrations/sqlite`, embedded at compile time
+static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations/sqlite");
+
+const PAPER_COLUMNS: &str = "id, url, arxiv_id, source, title, description, fetched_at, status, \
+    created_at, updated_at, change_seq";
+
+/// Matches ids from JSON array bound in place of `?`, keeps number of bound
+/// parameters constant however many ids are looked up
+const IDS_FROM_JSON: &str = "(SELECT value FROM json_each(?))";
+
+/// Row of [`models::PaperFull`], authors and subjects are aggregated into JSON
+#[derive(sqlx::FromRow)]
+struct PaperFullRow {
+    #[sqlx(flatten)]
+    paper: models::PaperSummary,
+    authors: sqlx::types::Json<Vec<models::Author>>,
+    subjects: sqlx::types::Json<Vec<models::Subject>>,
+}
+
+/// Single file store for small deployments and tests. Queries are checked at
+/// runtime since offline query data is prepared against Postgres only
+#[derive(Clone)]
+pub struct SqliteStore {
+    pool: sqlx::Pool<sqlx::Sqlite>,
+}
+
+impl SqliteStore {
+    /// Open database at `db_url`, e.g. `sqlite:papers.db` or `sqlite::memory:`,
+    /// creating the file if it does not exist
+    pub async fn new(db_url: &str, pool: &PoolConfig) -> Result<SqliteStore> {
+        let options = SqliteConnectOptions::from_str(db_url)?
+            .create_if_missing(true)
+            .foreign_keys(true);
+
+        // NOTE: every connection to an in-memory database opens a database of
+        // its own, which disappears with the connection. Keep a single one
+        // open for the whole life of the pool
+        let pool_options = if is_in_memory(db_url) {
+            SqlitePoolOptions::new()
+                .max_connections(1)
+                .min_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+        } else {
+            SqlitePoolOptions::new()
+                .max_connections(pool.max_connections)
+                .min_connections(pool.min_connections)
+                .idle_timeout(pool.idle_timeout)
+        };
+
+        Ok(SqliteStore {
+            pool: pool_options
+                .acquire_timeout(pool.acquire_timeout)
+                .connect_with(options)
+                .await?,
+        })
+    }
+
+    async fn upsert_names(
+        table: &str,
+        names: impl Iterator<Item = &String>,
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    ) -> Result<HashMap<String, models::Id>> {
+        let mut ids = HashMap::new();
+        for name in names {
+            if ids.contains_key(name) {
+                continue;
+            }
+            sqlx::query(&format!(
+                "INSERT INTO {table} (name) VALUES (?) ON CONFLICT(name) DO NOTHING"
+            ))
+            .bind(name)
+            .execute(&mut **tx)
+            .await?;
+            let id = sqlx::query_scalar(&format!("SELECT id FROM {table} WHERE name = ?"))
+                .bind(name)
+                .fetch_one(&mut **tx)
+                .await?;
+            ids.insert(name.clone(), id);
+        }
+
+        Ok(ids)
+    }
+
+    /// Delete rows attached to papers, foreign keys do not cascade in SQLite
+    async fn delete_paper_rows(
+        paper_ids: &[models::Id],
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    ) -> Result<()> {
+        for table in [
+            "paper_author",
+            "paper_subject",
+            "paper_captions",
+            "paper_changes",
+            "paper_embeddings",
+            "paper_bodies",
+        ] {
+            sqlx::query(&format!(
+                "DELETE FROM {table} WHERE paper_id IN {IDS_FROM_JSON}"
+            ))
+            .bind(sqlx::types::Json(paper_ids))
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl PaperStore for SqliteStore {
+    async fn migrate(&self) -> Result<()> {
+        log::debug!("DB: applying migrations");
+        MIGRATOR.run(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn get_all_papers(&self) -> Result<Vec<models::PaperSummary>> {
+        sqlx::query_as(&format!("SELECT {PAPER_COLUMNS} FROM papers"))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    async fn get_papers_page(
+        &self,
+        after_id: Option<models::Id>,
+        limit: i64,
+    ) -> Result<Vec<models::Paper>> {
+        let papers: Vec<models::PaperSummary> = sqlx::query_as(&format!(
+            "SELECT {PAPER_COLUMNS} FROM papers
+                WHERE ?1 IS NULL OR id > ?1
+                ORDER BY id
+                LIMIT ?2"
+        ))
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let paper_ids = papers.iter().map(|p| p.id).collect::<Vec<_>>();
+        let bodies = self.get_paper_bodies(&paper_ids).await?;
+
+        Ok(with_bodies(papers, bodies))
+    }
+
+    async fn count_papers(&self) -> Result<i64> {
+        sqlx::query_scalar("SELECT COUNT(*) FROM papers")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    async fn get_paper(&self, desired_id: models::Id) -> Result<Option<models::Paper>> {
+        let paper: Option<models::PaperSummary> =
+            sqlx::query_as(&format!("SELECT {PAPER_COLUMNS} FROM papers WHERE id = ?"))
+                .bind(desired_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        let Some(paper) = paper else {
+            return Ok(None);
+        };
+
+        let body = self
+            .get_paper_bodies(&[desired_id])
+            .await?
+            .remove(&desired_id)
+            .unwrap_or_default();
+
+        Ok(Some(paper.with_body(body)))
+    }
+
+    async fn get_paper_bodies(
+        &self,
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, String>> {
+        sqlx::query_as::<_, (models::Id, BodyCompression, i32, Vec<u8>)>(&format!(
+            "SELECT paper_id, compression, size, content
+                FROM paper_bodies WHERE paper_id IN {IDS_FROM_JSON}"
+        ))
+        .bind(sqlx::types::Json(paper_ids))
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|(paper_id, compression, size, content)| {
+            Ok((paper_id, decode_body(paper_id, compression, size, content)?))
+        })
+        .collect()
+    }
+
+    async fn get_paper_authors(&self, desired_paper_id: models::Id) -> Result<Vec<models::Author>> {
+        sqlx::query_as(
+            "SELECT authors.id, authors.name
+                FROM authors
+            JOIN paper_author ON authors.id = paper_author.author_id
+                WHERE paper_author.paper_id = ?",
+        )
+        .bind(desired_paper_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn get_paper_captions(&self, paper_id: models::Id) -> Result<Vec<models::Caption>> {
+        sqlx::query_as(
+            "SELECT id, paper_id, kind, number, text
+                FROM paper_captions
+                WHERE paper_id = ?
+                ORDER BY id",
+        )
+        .bind(paper_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn get_papers_by_ids(
+        &self,
+        ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, models::PaperSummary>> {
+        Ok(sqlx::query_as::<_, models::PaperSummary>(&format!(
+            "SELECT {PAPER_COLUMNS} FROM papers WHERE id IN {IDS_FROM_JSON}"
+        ))
+        .bind(sqlx::types::Json(ids))
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|paper| (paper.id, paper))
+        .collect())
+    }
+
+    async fn get_papers_full(
+        &self,
+        ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, models::PaperFull>> {
+        // NOTE: ordered `json_group_array` turns JSON objects into strings,
+        // arrays are joined by hand instead
+        Ok(sqlx::query_as::<_, PaperFullRow>(&format!(
+            "SELECT {PAPER_COLUMNS},
+                    (SELECT '[' || coalesce(group_concat(
+                            json_object('id', authors.id, 'name', authors.name), ','
+                            ORDER BY authors.id
+                        ), '') || ']'
+                        FROM authors
+                    JOIN paper_author ON authors.id = paper_author.author_id
+                        WHERE paper_author.paper_id = papers.id
+                    ) AS authors,
+                    (SELECT '[' || coalesce(group_concat(
+                            json_object('id', subjects.id, 'name', subjects.name), ','
+                            ORDER BY subjects.id
+                        ), '') || ']'
+                        FROM subjects
+                    JOIN paper_subject ON subjects.id = paper_subject.subject_id
+                        WHERE paper_subject.paper_id = papers.id
+                    ) AS subjects
+                FROM papers WHERE id IN {IDS_FROM_JSON}"
+        ))
+        .bind(sqlx::types::Json(ids))
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let full = models::PaperFull {
+                paper: row.paper,
+                authors: row.authors.0,
+                subjects: row.subjects.0,
+            };
+            (full.paper.id, full)
+        })
+        .collect())
+    }
+
+    async fn get_authors_for_papers(
+        &self,
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, Vec<models::Author>>> {
+        let mut authors = HashMap::<_, Vec<_>>::new();
+        for (paper_id, id, name) in sqlx::query_as::<_, (models::Id, models::Id, String)>(&format!(
+            "SELECT paper_author.paper_id, authors.id, authors.name
+                    FROM authors
+                JOIN paper_author ON authors.id = paper_author.author_id
+                    WHERE paper_author.paper_id IN {IDS_FROM_JSON}
+                    ORDER BY authors.id"
+        ))
+        .bind(sqlx::types::Json(paper_ids))
+        .fetch_all(&self.pool)
+        .await?
+        {
+            authors
+                .entry(paper_id)
+                .or_default()
+                .push(models::Author { id, name });
+        }
+
+        Ok(authors)
+    }
+
+    async fn get_subjects_for_papers(
+        &self,
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, Vec<models::Subject>>> {
+        let mut subjects = HashMap::<_, Vec<_>>::new();
+        for (paper_id, id, name) in sqlx::query_as::<_, (models::Id, models::Id, String)>(&format!(
+            "SELECT paper_subject.paper_id, subjects.id, subjects.name
+                    FROM subjects
+                JOIN paper_subject ON subjects.id = paper_subject.subject_id
+                    WHERE paper_subject.paper_id IN {IDS_FROM_JSON}
+                    ORDER BY subjects.id"
+        ))
+        .bind(sqlx::types::Json(paper_ids))
+        .fetch_all(&self.pool)
+        .await?
+        {
+            subjects
+                .entry(paper_id)
+                .or_default()
+                .push(models::Subject { id, name });
+        }
+
+        Ok(subjects)
+    }
+
+    async fn get_captions_for_papers(
+        &self,
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, Vec<models::Caption>>> {
+        let mut captions = HashMap::<_, Vec<_>>::new();
+        for caption in sqlx::query_as::<_, models::Caption>(&format!(
+            "SELECT id, paper_id, kind, number, text
+                FROM paper_captions
+                WHERE paper_id IN {IDS_FROM_JSON}
+                ORDER BY id"
+        ))
+        .bind(sqlx::types::Json(paper_ids))
+        .fetch_all(&self.pool)
+        .await?
+        {
+            captions.entry(caption.paper_id).or_default().push(caption);
+        }
+
+        Ok(captions)
+    }
+
+    async fn paper_exists(&self, desired_url: &str) -> Result<bool> {
+        sqlx::query_scalar(
+            "SELECT EXISTS(SELECT * FROM papers WHERE url = ?1)
+                OR EXISTS(SELECT * FROM takedowns WHERE url = ?1)",
+        )
+        .bind(desired_url)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn insert_papers_full(&self, mut papers: Vec<NewPaperFull>) -> Result<HashSet<String>> {
+        dedup_papers(&mut papers);
+        if papers.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        log::trace!("DB: inserting batch of {} papers", papers.len());
+
+        let mut tx = self.pool.begin().await?;
+        let fetched_at = chrono::Utc::now();
+
+        let mut inserted = HashSet::new();
+        for NewPaperFull {
+            paper,
+            authors,
+            subjects,
+            captions,
+        } in &papers
+        {
+            let paper_id: Option<models::Id> = sqlx::query_scalar(
+                "INSERT INTO papers (url, arxiv_id, source, status, title, description, fetched_at)
+                    SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7
+                    WHERE NOT EXISTS(SELECT * FROM takedowns
+                        WHERE takedowns.url = ?1 OR takedowns.arxiv_id = ?2)
+                    ON CONFLICT DO NOTHING
+                    RETURNING id",
+            )
+            .bind(&paper.url)
+            .bind(&paper.arxiv_id)
+            .bind(&paper.source)
+            .bind(paper.status)
+            .bind(&paper.title)
+            .bind(&paper.description)
+            .bind(fetched_at)
+            .fetch_optional(&mut *tx)
+            .await?;
+            let Some(paper_id) = paper_id else {
+                log::debug!("DB: paper {:?} already exists, skipping", paper.url);
+                continue;
+            };
+            inserted.insert(paper.url.clone());
+
+            let (compression, size, content) = encode_body(&paper.body);
+            sqlx::query(
+                "INSERT INTO paper_bodies (paper_id, compression, size, content)
+                    VALUES (?, ?, ?, ?)",
+            )
+            .bind(paper_id)
+            .bind(compression)
+            .bind(size)
+            .bind(content)
+            .execute(&mut *tx)
+            .await?;
+
+            let author_ids =
+                Self::upsert_names("authors", authors.iter().map(|a| &a.name), &mut tx).await?;
+            for author_id in author_ids.values() {
+                sqlx::query(
+                    "INSERT INTO paper_author (paper_id, author_id) VALUES (?, ?)
+                        ON CONFLICT DO NOTHING",
+                )
+                .bind(paper_id)
+                .bind(author_id)
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            let subject_ids =
+                Self::upsert_names("subjects", subjects.iter().map(|s| &s.name), &mut tx).await?;
+            for subject_id in subject_ids.values() {
+                sqlx::query(
+                    "INSERT INTO paper_subject (paper_id, subject_id) VALUES (?, ?)
+                        ON CONFLICT DO NOTHING",
+                )
+                .bind(paper_id)
+                .bind(subject_id)
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            for caption in captions {
+                sqlx::query(
+                    "INSERT INTO paper_captions (paper_id, kind, number, text)
+                        VALUES (?, ?, ?, ?)",
+                )
+                .bind(paper_id)
+                .bind(caption.kind)
+                .bind(&caption.number)
+                .bind(&caption.text)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(inserted)
+    }
+
+    async fn insert_scrape_run(&self, run: NewScrapeRun) -> Result<models::Id> {
+        log::trace!("DB: inserting scrape run started at {:?}", run.started_at);
+        Ok(sqlx::query_scalar(
+            "INSERT INTO scrape_runs (started_at, finished_at, query, pages_scraped,
+                    papers_found, papers_stored, papers_failed, bytes_downloaded, failures)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                RETURNING id",
+        )
+        .bind(run.started_at)
+        .bind(run.finished_at)
+        .bind(run.query)
+        .bind(run.pages_scraped)
+        .bind(run.papers_found)
+        .bind(run.papers_stored)
+        .bind(run.papers_failed)
+        .bind(run.bytes_downloaded)
+        .bind(sqlx::types::Json(&run.failures))
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    async fn select_papers(&self, filter: &PaperFilter) -> Result<Vec<PaperRef>> {
+        let mut query = sqlx::QueryBuilder::new("SELECT papers.id, papers.url FROM papers WHERE 1");
+        if let Some(ids) = &filter.ids {
+            query.push(" AND papers.id IN (");
+            let mut separated = query.separated(", ");
+            for id in ids {
+                separated.push_bind(id);
+            }
+            // NOTE: `IN ()` is valid in SQLite and matches nothing
+            query.push(")");
+        }
+        if let Some(fetched_before) = filter.fetched_before {
+            query
+                .push(" AND papers.fetched_at < ")
+                .push_bind(fetched_before);
+        }
+        if let Some(subject) = &filter.subject {
+            query
+                .push(
+                    " AND EXISTS(SELECT * FROM paper_subject
+                        JOIN subjects ON subjects.id = paper_subject.subject_id
+                        WHERE paper_subject.paper_id = papers.id AND subjects.name = ",
+                )
+                .push_bind(subject)
+                .push(")");
+        }
+        query
+            .push(" ORDER BY papers.fetched_at LIMIT ")
+            .push_bind(filter.limit.unwrap_or(-1));
+
+        query
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    async fn get_embeddings(
+        &self,
+        model: &str,
+        paper_ids: &[models::Id],
+    ) -> Result<HashMap<models::Id, models::PaperEmbedding>> {
+        Ok(
+            sqlx::query_as::<_, (models::Id, String, String, sqlx::types::Json<Vec<f32>>)>(
+                &format!(
+                    "SELECT paper_id, model, content_hash, embedding
+                        FROM paper_embeddings
+                        WHERE model = ? AND paper_id IN {IDS_FROM_JSON}"
+                ),
+            )
+            .bind(model)
+            .bind(sqlx::types::Json(paper_ids))
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|(paper_id, model, content_hash, embedding)| {
+                let embedding = models::PaperEmbedding {
+                    paper_id,
+                    model,
+                    content_hash,
+                    embedding: embedding.0,
+                };
+                (paper_id, embedding)
+            })
+            .collect(),
+        )
+    }
+
+    async fn upsert_embeddings(&self, embeddings: Vec<models::PaperEmbedding>) -> Result<()> {
+        log::trace!("DB: storing {} embeddings", embeddings.len());
+
+        let mut tx = self.pool.begin().await?;
+        for e in embeddings {
+            sqlx::query(
+                "INSERT INTO paper_embeddings (paper_id, model, dimension, content_hash, embedding)
+                    VALUES (?, ?, ?, ?, ?)
+                    ON CONFLICT (paper_id, model) DO UPDATE
+                    SET dimension = excluded.dimension,
+                        content_hash = excluded.content_hash,
+                        embedding = excluded.embedding",
+            )
+            .bind(e.paper_id)
+            .bind(&e.model)
+            .bind(e.embedding.len() as i32)
+            .bind(&e.content_hash)
+            .bind(sqlx::types::Json(&e.embedding))
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn nearest_papers(
+        &self,
+        model: &str,
+        embedding: &[f32],
+        limit: i64,
+    ) -> Result<Vec<models::SearchHit>> {
+        let mut hits = sqlx::query_as::<_, (models::Id, sqlx::types::Json<Vec<f32>>)>(
+            "SELECT paper_id, embedding FROM paper_embeddings WHERE model = ? AND dimension = ?",
+        )
+        .bind(model)
+        .bind(embedding.len() as i32)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|(paper_id, other)| models::SearchHit {
+            paper_id,
+            score: cosine_similarity(embedding, &other),
+        })
+        .collect::<Vec<_>>();
+
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(limit.try_into().unwrap_or(0));
+
+        Ok(hits)
+    }
+
+    async fn changes_since(&self, seq: i64, limit: i64) -> Result<Vec<models::ChangeEvent>> {
+        sqlx::query_as(
+            "SELECT id AS paper_id, change_seq, updated_at, false AS deleted
+                FROM papers WHERE change_seq > ?1
+            UNION ALL
+            SELECT paper_id, change_seq, taken_down_at, true
+                FROM takedowns WHERE change_seq > ?1
+            UNION ALL
+            SELECT paper_id, change_seq, deleted_at, true
+                FROM paper_deletions WHERE change_seq > ?1
+            ORDER BY change_seq
+            LIMIT ?2",
+        )
+        .bind(seq)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn recent_changes(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+        seq: i64,
+    ) -> Result<Vec<models::ChangeEvent>> {
+        // NOTE: timestamps set by triggers and bound ones are formatted apart,
+        // `julianday` compares them as time
+        sqlx::query_as(
+            "SELECT id AS paper_id, change_seq, updated_at, false AS deleted
+                FROM papers WHERE julianday(updated_at) >= julianday(?1) AND change_seq <= ?2
+            UNION ALL
+            SELECT paper_id, change_seq, taken_down_at, true
+                FROM takedowns
+                WHERE julianday(taken_down_at) >= julianday(?1) AND change_seq <= ?2
+            UNION ALL
+            SELECT paper_id, change_seq, deleted_at, true
+                FROM paper_deletions
+                WHERE julianday(deleted_at) >= julianday(?1) AND change_seq <= ?2
+            ORDER BY change_seq",
+        )
+        .bind(since)
+        .bind(seq)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    async fn subscribe_changes(&self) -> Result<BoxStream<'static, Result<models::ChangeEvent>>> {
+        Err(Error::Unsupported("change notifications"))
+    }
+
+    async fn take_down_paper(
+        &self,
+        paper_id: models::Id,
+        reason: &str,
+    ) -> Result<Option<models::Takedown>> {
+        log::info!("DB: taking down paper {paper_id:?}: {reason}");
+
+        let mut tx = self.pool.begin().await?;
+
+        Self::delete_paper_rows(&[paper_id], &mut tx).await?;
+
+        let deleted: Option<(String, Option<ArxivId>)> =
+            sqlx::query_as("DELETE FROM papers WHERE id = ? RETURNING url, arxiv_id")
+                .bind(paper_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        let Some((url, arxiv_id)) = deleted else {
+            return Ok(None);
+        };
+
+        let takedown_id: models::Id = sqlx::query_scalar(
+            "INSERT INTO takedowns (paper_id, url, arxiv_id, reason, taken_down_at)
+                VALUES (?, ?, ?, ?, ?)
+                RETURNING id",
+        )
+        .bind(paper_id)
+        .bind(url)
+        .bind(arxiv_id)
+        .bind(reason)
+        .bind(chrono::Utc::now())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // NOTE: change sequence is set by trigger after RETURNING is evaluated
+        let takedown = sqlx::query_as("SELECT * FROM takedowns WHERE id = ?")
+            .bind(takedown_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(takedown))
+    }
+
+    async fn get_takedowns(&self) -> Result<Vec<models::Takedown>> {
+        sqlx::query_as("SELECT * FROM takedowns ORDER BY id")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| e.into())
+    }
+
+    async fn insert_takedowns(&self, takedowns: Vec<models::NewTakedown>) -> Result<u64> {
+        log::trace!("DB: inserting {} takedowns", takedowns.len());
+
+        let mut tx = self.pool.begin().await?;
+
+        let mut inserted = 0;
+        for takedown in takedowns {
+            inserted += sqlx::query(
+                "INSERT INTO takedowns (paper_id, url, arxiv_id, reason, taken_down_at)
+                    VALUES (0, ?, ?, ?, ?)
+                    ON CONFLICT DO NOTHING",
+            )
+            .bind(takedown.url)
+            .bind(takedown.arxiv_id)
+            .bind(takedown.reason)
+            .bind(takedown.taken_down_at)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+
+        tx.commit().await?;
+
+        Ok(inserted)
+    }
+
+    async fn delete_papers(&self, paper_ids: &[models::Id]) -> Result<u64> {
+        log::info!("DB: deleting papers {paper_ids:?}");
+
+        let mut tx = self.pool.begin().await?;
+
+        Self::delete_paper_rows(paper_ids, &mut tx).await?;
+        sqlx::query(&format!(
+            "INSERT INTO paper_deletions (paper_id, deleted_at)
+                SELECT id, ? FROM papers WHERE id IN {IDS_FROM_JSON}"
+        ))
+        .bind(chrono::Utc::now())
+        .bind(sqlx::types::Json(paper_ids))
+        .execute(&mut *tx)
+        .await?;
+        let deleted = sqlx::query(&format!("DELETE FROM papers WHERE id IN {IDS_FROM_JSON}"))
+            .bind(sqlx::types::Json(paper_ids))
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        tx.commit().await?;
+
+        Ok(deleted)
+    }
+
+    async fn check_integrity(&self, embedding_model: Option<&str>) -> Result<Vec<IntegrityIssue>> {
+        let mut issues = Vec::new();
+
+        issues.extend(
+            sqlx::query_as::<_, (models::Id, String)>(
+                "SELECT id, name FROM authors
+                    WHERE NOT EXISTS(SELECT * FROM paper_author WHERE author_id = authors.id)",
+            )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|(id, name)| IntegrityIssue::OrphanAuthor { id, name }),
+        );
+
+        issues.extend(
+            sqlx::query_as::<_, (models::Id, String)>(
+                "SELECT id, name FROM subjects
+                    WHERE NOT EXISTS(SELECT * FROM paper_subject WHERE subject_id = subjects.id)",
+            )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|(id, name)| IntegrityIssue::OrphanSubject { id, name }),
+        );
+
+        issues.extend(
+            sqlx::query_as::<_, (models::Id, String)>(
+                "SELECT id, url FROM papers WHERE trim(title) = ''",
+            )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|(paper_id, url)| IntegrityIssue::EmptyTitle { paper_id, url }),
+        );
+
+        issues.extend(
+            sqlx::query_as::<_, (models::Id, String)>(
+                "SELECT id, url FROM papers
+                    WHERE NOT EXISTS(SELECT * FROM paper_bodies
+                        WHERE paper_id = papers.id AND size > 0)",
+            )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|(paper_id, url)| IntegrityIssue::EmptyBody { paper_id, url }),
+        );
+
+        issues.extend(find_duplicates(
+            sqlx::query_as("SELECT id, url FROM papers")
+                .fetch_all(&self.pool)
+                .await?,
+        ));
+
+        if let Some(model) = embedding_model {
+            issues.extend(
+                sqlx::query_as::<_, (models::Id, String)>(
+                    "SELECT paper_id, model FROM paper_embeddings WHERE model <> ?",
+                )
+                .bind(model)
+                .fetch_all(&self.pool)
+                .await?
+                .into_iter()
+                .map(|(paper_id, model)| IntegrityIssue::StaleEmbedding { paper_id, model }),
+            );
+        }
+
+        Ok(issues)
+    }
+
+    async fn delete_orphans(&self) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+
+        let authors = sqlx::query(
+            "DELETE FROM authors
+                WHERE NOT EXISTS(SELECT * FROM paper_author WHERE author_id = authors.id)",
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        let subjects = sqlx::query(
+            "DELETE FROM subjects
+                WHERE NOT EXISTS(SELECT * FROM paper_subject WHERE subject_id = subjects.id)",
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        tx.commit().await?;
+
+        Ok(authors + subjects)
+    }
+
+    async fn delete_stale_embeddings(&self, model: &str) -> Result<u64> {
+        Ok(sqlx::query("DELETE FROM paper_embeddings WHERE model <> ?")
+            .bind(model)
+            .execute(&mut *self.pool.acquire().await?)
+            .await?
+            .rows_affected())
+    }
+
+    async fn search_papers(
+        &self,
+        _query: &str,
+        _limit: i64,
+        _include_withdrawn: bool,
+    ) -> Result<Vec<models::SearchHit>> {
+        Err(Error::Unsupported("full-text search"))
+    }
+
+    async fn update_paper_metadata(
+        &self,
+        paper_id: models::Id,
+        update: PaperMetadataUpdate,
+    ) -> Result<Vec<PaperChange>> {
+        log::trace!("DB: updating metadata of paper {paper_id:?}");
+
+        let mut tx = self.pool.begin().await?;
+
+        let (title, description, status): (String, String, PaperStatus) =
+            sqlx::query_as("SELECT title, description, status FROM papers WHERE id = ?")
+                .bind(paper_id)
+                .fetch_one(&mut *tx)
+                .await?;
+
+        let authors = sqlx::query_scalar(
+            "SELECT authors.name FROM authors
+            JOIN paper_author ON authors.id = paper_author.author_id
+                WHERE paper_author.paper_id = ?",
+        )
+        .bind(paper_id)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .collect();
+
+        let subjects = sqlx::query_scalar(
+            "SELECT subjects.name FROM subjects
+            JOIN paper_subject ON subjects.id = paper_subject.subject_id
+                WHERE paper_subject.paper_id = ?",
+        )
+        .bind(paper_id)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .collect();
+
+        let MetadataDiff {
+            changes,
+            paper_changed,
+            added_authors,
+            removed_authors,
+            added_subjects,
+            removed_subjects,
+        } = diff_metadata(
+            StoredMetadata {
+                title,
+                description,
+                status,
+                authors,
+                subjects,
+            },
+            &update,
+        );
+
+        let now = chrono::Utc::now();
+        if paper_changed {
+            sqlx::query(
+                "UPDATE papers SET title = ?, description = ?, status = ?, fetched_at = ?
+                    WHERE id = ?",
+            )
+            .bind(&update.title)
+            .bind(&update.description)
+            .bind(update.status)
+            .bind(now)
+            .bind(paper_id)
+            .execute(&mut *tx)
+            .await?;
+        } else {
+            sqlx::query("UPDATE papers SET fetched_at = ? WHERE id = ?")
+                .bind(now)
+                .bind(paper_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        if changes.is_empty() {
+            tx.commit().await?;
+            return Ok(changes);
+        }
+
+        let author_ids = Self::upsert_names("authors", added_authors.iter(), &mut tx).await?;
+        for author_id in author_ids.values() {
+            sqlx::query(
+                "INSERT INTO paper_author (paper_id, author_id) VALUES (?, ?)
+                    ON CONFLICT DO NOTHING",
+            )
+            .bind(paper_id)
+            .bind(author_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        for name in &removed_authors {
+            sqlx::query(
+                "DELETE FROM paper_author
+                    WHERE paper_id = ?
+                        AND author_id = (SELECT id FROM authors WHERE name = ?)",
+            )
+            .bind(paper_id)
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let subject_ids = Self::upsert_names("subjects", added_subjects.iter(), &mut tx).await?;
+        for subject_id in subject_ids.values() {
+            sqlx::query(
+                "INSERT INTO paper_subject (paper_id, subject_id) VALUES (?, ?)
+                    ON CONFLICT DO NOTHING",
+            )
+            .bind(paper_id)
+            .bind(subject_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        for name in &removed_subjects {
+            sqlx::query(
+                "DELETE FROM paper_subject
+                    WHERE paper_id = ?
+                        AND subject_id = (SELECT id FROM subjects WHERE name = ?)",
+            )
+            .bind(paper_id)
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for change in &changes {
+            sqlx::query(
+                "INSERT INTO paper_changes (paper_id, changed_at, field, old_value, new_value)
+                    VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(paper_id)
+            .bind(now)
+            .bind(&change.field)
+            .bind(&change.old_value)
+            .bind(&change.new_value)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(changes)
+    }
+}
+
+/// Whether `db_url` names an in-memory database, e.g. `sqlite::memory:` or
+/// `sqlite:file:papers?mode=memory`
+fn is_in_memory(db_url: &str) -> bool {
+    db_url.contains(":memory:") || db_url.contains("mode=memory")
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot = a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (norm(a) * norm(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NewAuthor, NewPaper, NewSubject, NewTakedown};
+
+    async fn store() -> SqliteStore {
+        let store = SqliteStore::new("sqlite::memory:", &PoolConfig::default())
+            .await
+            .unwrap();
+        store.migrate().await.unwrap();
+        store
+    }
+
+    fn paper(arxiv_id: &str, authors: &[&str], subjects: &[&str]) -> NewPaperFull {
+        NewPaperFull {
+            paper: NewPaper {
+                url: format!("https://arxiv.org/abs/{arxiv_id}"),
+                arxiv_id: Some(arxiv_id.parse().unwrap()),
+                source: "arxiv".to_string(),
+                status: PaperStatus::Active,
+                title: format!("Paper {arxiv_id}"),
+                body: "Body. ".repeat(1000),
+                description: "Abstract".to_string(),
+            },
+            authors: authors
+                .iter()
+                .map(|name| NewAuthor {
+                    name: name.to_string(),
+                })
+                .collect(),
+            subjects: subjects
+                .iter()
+                .map(|name| NewSubject {
+                    name: name.to_string(),
+                })
+                .collect(),
+            captions: Vec::new(),
+        }
+    }
+
+    async fn paper_id(store: &SqliteStore, arxiv_id: &str) -> models::Id {
+        sqlx::query_scalar("SELECT id FROM papers WHERE arxiv_id = ?")
+            .bind(arxiv_id)
+            .fetch_one(&store.pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn in_memory_store_outlives_connections() {
+        let store = SqliteStore::new(
+            "sqlite::memory:",
+            &PoolConfig {
+                max_connections: 4,
+                ..PoolConfig::default()
+            },
+        )
+        .await
+        .unwrap();
+        store.migrate().await.unwrap();
+
+        // NOTE: with more than one connection queries may land on a database
+        // without the schema
+        let counts = futures::future::join_all((0..4).map(|_| store.count_papers())).await;
+        assert!(counts.into_iter().all(|count| count.unwrap() == 0));
+    }
+
+    #[tokio::test]
+    async fn insert_skips_duplicates() {
+        let store = store().await;
+
+        let inserted = store
+            .insert_papers_full(vec![
+                paper("2101.00001", &["Ada"], &["cs.LG"]),
+                paper("2101.00002", &["Ada", "Bob"], &["cs.LG"]),
+                paper("2101.00001", &["Eve"], &[]),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(inserted.len(), 2);
+
+        let inserted = store
+            .insert_papers_full(vec![
+                paper("2101.00002", &[], &[]),
+                paper("2101.00003", &[], &[]),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(
+            inserted,
+            HashSet::from(["https://arxiv.org/abs/2101.00003".to_string()])
+        );
+        assert_eq!(store.count_papers().await.unwrap(), 3);
+
+        let id = paper_id(&store, "2101.00002").await;
+        let paper = store.get_paper(id).await.unwrap().unwrap();
+        assert_eq!(paper.body, "Body. ".repeat(1000));
+        let mut authors = store
+            .get_paper_authors(id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|a| a.name)
+            .collect::<Vec<_>>();
+        authors.sort();
+        assert_eq!(authors, ["Ada", "Bob"]);
+    }
+
+    #[tokio::test]
+    async fn full_papers_list_links_in_order() {
+        let store = store().await;
+        store
+            .insert_papers_full(vec![
+                paper("2101.00001", &["Ada", "Bob", "Cy"], &["cs.LG", "cs.CL"]),
+                paper(
+                    "2101.00002",
+                    &["Cy", "Bob", "Ada", "Dee"],
+                    &["stat.ML", "cs.CL"],
+                ),
+            ])
+            .await
+            .unwrap();
+        let ids = [
+            paper_id(&store, "2101.00001").await,
+            paper_id(&store, "2101.00002").await,
+        ];
+
+        let full = store.get_papers_full(&ids).await.unwrap();
+        let mut authors = store.get_authors_for_papers(&ids).await.unwrap();
+        let mut subjects = store.get_subjects_for_papers(&ids).await.unwrap();
+        for id in ids {
+            let author_ids = full[&id].authors.iter().map(|a| a.id).collect::<Vec<_>>();
+            assert!(author_ids.windows(2).all(|w| w[0] < w[1]));
+            assert_eq!(
+                author_ids,
+                authors
+                    .remove(&id)
+                    .unwrap()
+                    .iter()
+                    .map(|a| a.id)
+                    .collect::<Vec<_>>()
+            );
+            let subject_ids = full[&id].subjects.iter().map(|s| s.id).collect::<Vec<_>>();
+            assert!(subject_ids.windows(2).all(|w| w[0] < w[1]));
+            assert_eq!(
+                subject_ids,
+                subjects
+                    .remove(&id)
+                    .unwrap()
+                    .iter()
+                    .map(|s| s.id)
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn takedown_leaves_tombstone() {
+        let store = store().await;
+        store
+            .insert_papers_full(vec![paper("2101.00001", &["Ada"], &["cs.LG"])])
+            .await
+            .unwrap();
+        let id = paper_id(&store, "2101.00001").await;
+
+        let takedown = store
+            .take_down_paper(id, "copyright")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(takedown.paper_id, id);
+        assert!(store.get_paper(id).await.unwrap().is_none());
+        assert!(store.take_down_paper(id, "again").await.unwrap().is_none());
+
+        // NOTE: another version of the same paper is refused as well
+        let mut again = paper("2101.00001", &[], &[]);
+        again.paper.url = "https://export.arxiv.org/abs/2101.00001".to_string();
+        assert!(store
+            .insert_papers_full(vec![paper("2101.00001", &[], &[]), again])
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(store
+            .paper_exists("https://arxiv.org/abs/2101.00001")
+            .await
+            .unwrap());
+
+        let restored = store
+            .insert_takedowns(vec![
+                NewTakedown {
+                    url: takedown.url.clone(),
+                    arxiv_id: takedown.arxiv_id.clone(),
+                    reason: "copyright".to_string(),
+                    taken_down_at: takedown.taken_down_at,
+                },
+                NewTakedown {
+                    url: "https://arxiv.org/abs/2101.00002".to_string(),
+                    arxiv_id: Some("2101.00002".parse().unwrap()),
+                    reason: "spam".to_string(),
+                    taken_down_at: chrono::Utc::now(),
+                },
+            ])
+            .await
+            .unwrap();
+        assert_eq!(restored, 1);
+        let takedowns = store.get_takedowns().await.unwrap();
+        assert_eq!(
+            takedowns.iter().map(|t| t.paper_id).collect::<Vec<_>>(),
+            [id, 0]
+        );
+    }
+
+    #[tokio::test]
+    async fn update_metadata_records_differences() {
+        let store = store().await;
+        store
+            .insert_papers_full(vec![paper("2101.00001", &["Ada", "Bob"], &["cs.LG"])])
+            .await
+            .unwrap();
+        let id = paper_id(&store, "2101.00001").await;
+        let seq = store.changes_since(0, 10).await.unwrap()[0].change_seq;
+
+        let update = PaperMetadataUpdate {
+            title: "Paper 2101.00001".to_string(),
+            description: "Abstract".to_string(),
+            status: PaperStatus::Active,
+            authors: vec![NewAuthor {
+                name: "Ada".to_string(),
+            }],
+            subjects: vec![
+                NewSubject {
+                    name: "cs.LG".to_string(),
+                },
+                NewSubject {
+                    name: "cs.CL".to_string(),
+                },
+            ],
+        };
+        let mut changes = store
+            .update_paper_metadata(id, update.clone())
+            .await
+            .unwrap();
+        changes.sort_by(|a, b| a.field.cmp(&b.field));
+        assert_eq!(
+            changes,
+            [
+                PaperChange {
+                    field: "author".to_string(),
+                    old_value: Some("Bob".to_string()),
+                    new_value: None,
+                },
+                PaperChange {
+                    field: "subject".to_string(),
+                    old_value: None,
+                    new_value: Some("cs.CL".to_string()),
+                },
+            ]
+        );
+        let changed = store.changes_since(seq, 10).await.unwrap();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].paper_id, id);
+
+        // NOTE: unchanged metadata only moves `fetched_at`
+        let seq = changed[0].change_seq;
+        assert!(store
+            .update_paper_metadata(id, update.clone())
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(store.changes_since(seq, 10).await.unwrap().is_empty());
+
+        let changes = store
+            .update_paper_metadata(
+                id,
+                PaperMetadataUpdate {
+                    status: PaperStatus::Withdrawn,
+                    ..update
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            changes,
+            [PaperChange {
+                field: "status".to_string(),
+                old_value: Some("active".to_string()),
+                new_value: Some("withdrawn".to_string()),
+            }]
+        );
+        let paper = store.get_paper(id).await.unwrap().unwrap();
+        assert_eq!(paper.status, PaperStatus::Withdrawn);
+    }
+
+    #[tokio::test]
+    async fn changes_are_listed_in_order() {
+        let store = store().await;
+        store
+            .insert_papers_full(vec![
+                paper("2101.00001", &["Ada"], &[]),
+                paper("2101.00002", &["Bob"], &[]),
+            ])
+            .await
+            .unwrap();
+        let first = paper_id(&store, "2101.00001").await;
+        let second = paper_id(&store, "2101.00002").await;
+
+        let changes = store.changes_since(0, 10).await.unwrap();
+        assert_eq!(
+            changes.iter().map(|c| c.paper_id).collect::<Vec<_>>(),
+            [first, second]
+        );
+        assert!(changes.iter().all(|c| !c.deleted));
+        assert!(changes[0].change_seq < changes[1].change_seq);
+        assert_eq!(store.changes_since(0, 1).await.unwrap().len(), 1);
+
+        let last_seq = changes[1].change_seq;
+        assert!(store.changes_since(last_seq, 10).await.unwrap().is_empty());
+
+        store.take_down_paper(first, "spam").await.unwrap();
+        let changes = store.changes_since(last_seq, 10).await.unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].paper_id, first);
+        assert!(changes[0].deleted);
+        assert!(changes[0].change_seq > last_seq);
+
+        // NOTE: deleting leaves no tombstone, but is listed as change as well
+        let last_seq = changes[0].change_seq;
+        assert_eq!(store.delete_papers(&[second, 1000]).await.unwrap(), 1);
+        let changes = store.changes_since(last_seq, 10).await.unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].paper_id, second);
+        assert!(changes[0].deleted);
+        assert!(!store
+            .paper_exists("https://arxiv.org/abs/2101.00002")
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn recent_changes_are_found_by_time() {
+        let store = store().await;
+        let pause = || tokio::time::sleep(std::time::Duration::from_millis(20));
+        store
+            .insert_papers_full(vec![paper("2101.00001", &[], &[])])
+            .await
+            .unwrap();
+        pause().await;
+        let since = chrono::Utc::now();
+        pause().await;
+        store
+            .insert_papers_full(vec![
+                paper("2101.00002", &[], &[]),
+                paper("2101.00003", &[], &[]),
+            ])
+            .await
+            .unwrap();
+        let changes = store.changes_since(0, 10).await.unwrap();
+        let seqs = changes.iter().map(|c| c.change_seq).collect::<Vec<_>>();
+
+        let recent = store.recent_changes(since, seqs[2]).await.unwrap();
+        assert_eq!(
+            recent.iter().map(|c| c.paper_id).collect::<Vec<_>>(),
+            [changes[1].paper_id, changes[2].paper_id]
+        );
+        let recent = store.recent_changes(since, seqs[1]).await.unwrap();
+        assert_eq!(
+            recent.iter().map(|c| c.paper_id).collect::<Vec<_>>(),
+            [changes[1].paper_id]
+        );
+
+        // NOTE: tombstones and deletions carry their own time
+        store
+            .take_down_paper(changes[0].paper_id, "spam")
+            .await
+            .unwrap();
+        store.delete_papers(&[changes[1].paper_id]).await.unwrap();
+        let recent = store.recent_changes(since, i64::MAX).await.unwrap();
+        assert_eq!(
+            recent
+                .iter()
+                .map(|c| (c.paper_id, c.deleted))
+                .collect::<Vec<_>>(),
+            [
+                (changes[2].paper_id, false),
+                (changes[0].paper_id, true),
+                (changes[1].paper_id, true),
+            ]
+        );
+    }
+}
@@ -1,2 +1,3 @@
+pub mod arxiv_id;
 pub mod db;
 pub mod models;
@@ -1,52 +1,399 @@
+use std::collections::HashMap;
+
+use crate::arxiv_id::ArxivId;
+
 pub type Id = i32;
 
-#[derive(PartialEq, Debug, sqlx::FromRow)]
+/// Whether paper is still available on its source
+#[derive(
+    Clone, Copy, PartialEq, Eq, Debug, Default, serde::Serialize, serde::Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "paper_status", rename_all = "lowercase")]
+pub enum PaperStatus {
+    #[default]
+    Active,
+    /// Authors withdrew the paper
+    Withdrawn,
+    /// Paper was replaced by another submission
+    Superseded,
+}
+
+impl PaperStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaperStatus::Active => "active",
+            PaperStatus::Withdrawn => "withdrawn",
+            PaperStatus::Superseded => "superseded",
+        }
+    }
+}
+
+impl sqlx::postgres::PgHasArrayType for PaperStatus {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_paper_status")
+    }
+}
+
+impl std::fmt::Display for PaperStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Kind of captioned element of a paper
+#[derive(
+    Clone, Copy, PartialEq, Eq, Hash, Debug, serde::Serialize, serde::Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "caption_kind", rename_all = "lowercase")]
+pub enum CaptionKind {
+    Figure,
+    Table,
+}
+
+impl CaptionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CaptionKind::Figure => "figure",
+            CaptionKind::Table => "table",
+        }
+    }
+}
+
+impl sqlx::postgres::PgHasArrayType for CaptionKind {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_caption_kind")
+    }
+}
+
+impl std::fmt::Display for CaptionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How paper body is stored in `paper_bodies`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "body_compression", rename_all = "lowercase")]
+pub enum BodyCompression {
+    /// UTF-8 text as is
+    None,
+    /// UTF-8 text compressed with zstd
+    Zstd,
+}
+
+impl sqlx::postgres::PgHasArrayType for BodyCompression {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_body_compression")
+    }
+}
+
+/// Paper together with its full text
+#[derive(PartialEq, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Paper {
     pub id: Id,
     pub url: String,
+    /// Unversioned identifier, `None` for papers from other sources
+    pub arxiv_id: Option<ArxivId>,
+    /// Preprint server the paper was scraped from
+    pub source: String,
     pub title: String,
     pub description: String,
     pub body: String,
+    /// Last time metadata was fetched from the source
+    pub fetched_at: chrono::DateTime<chrono::Utc>,
+    pub status: PaperStatus,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Last time paper, its authors or subjects changed
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    /// Position of the last change of the paper in the change feed
+    pub change_seq: i64,
+}
+
+impl Paper {
+    /// Split into metadata and body
+    pub fn split_body(self) -> (PaperSummary, String) {
+        let summary = PaperSummary {
+            id: self.id,
+            url: self.url,
+            arxiv_id: self.arxiv_id,
+            source: self.source,
+            title: self.title,
+            description: self.description,
+            fetched_at: self.fetched_at,
+            status: self.status,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            change_seq: self.change_seq,
+        };
+        (summary, self.body)
+    }
+}
+
+/// Paper without its full text, which is stored separately and is by far the
+/// largest part of a paper
+#[derive(PartialEq, Clone, Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct PaperSummary {
+    pub id: Id,
+    pub url: String,
+    /// Unversioned identifier, `None` for papers from other sources
+    pub arxiv_id: Option<ArxivId>,
+    /// Preprint server the paper was scraped from
+    pub source: String,
+    pub title: String,
+    pub description: String,
+    /// Last time metadata was fetched from the source
+    pub fetched_at: chrono::DateTime<chrono::Utc>,
+    pub status: PaperStatus,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Last time paper, its authors or subjects changed
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    /// Position of the last change of the paper in the change feed
+    pub change_seq: i64,
+}
+
+impl PaperSummary {
+    pub fn with_body(self, body: String) -> Paper {
+        Paper {
+            id: self.id,
+            url: self.url,
+            arxiv_id: self.arxiv_id,
+            source: self.source,
+            title: self.title,
+            description: self.description,
+            body,
+            fetched_at: self.fetched_at,
+            status: self.status,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            change_seq: self.change_seq,
+        }
+    }
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
 pub struct Author {
     pub id: Id,
     pub name: String,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Subject {
     pub id: Id,
     pub name: String,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct Caption {
+    pub id: Id,
+    pub paper_id: Id,
+    pub kind: CaptionKind,
+    /// Label as printed in the paper, e.g. `3` or `A.1`
+    pub number: String,
+    pub text: String,
+}
+
+/// Names of authors as listed in a paper, e.g. `Alice, Bob`
+pub fn byline(authors: &[Author]) -> String {
+    authors
+        .iter()
+        .map(|a| a.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Paper with its authors and subjects, see
+/// [`crate::db::PaperStore::get_papers_full`]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct PaperFull {
+    pub paper: PaperSummary,
+    pub authors: Vec<Author>,
+    pub subjects: Vec<Subject>,
+}
+
+impl PaperFull {
+    pub fn byline(&self) -> String {
+        byline(&self.authors)
+    }
+}
+
+/// Paper together with everything indexed along with it
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct PaperWithAuthors {
+    pub paper: Paper,
+    pub authors: Vec<Author>,
+    pub captions: Vec<Caption>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct PaperEmbedding {
+    pub paper_id: Id,
+    /// Name of the model which computed the embedding
+    pub model: String,
+    /// Hash of the embedded text
+    pub content_hash: String,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct PaperAuthor {
     pub paper_id: Id,
     pub author_id: Id,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct PaperSubject {
     pub paper_id: Id,
     pub subject_id: Id,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct NewPaper {
     pub url: String,
+    pub arxiv_id: Option<ArxivId>,
+    pub source: String,
+    pub status: PaperStatus,
     pub title: String,
     pub body: String,
     pub description: String,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct NewAuthor {
     pub name: String,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct NewSubject {
     pub name: String,
 }
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct NewCaption {
+    pub kind: CaptionKind,
+    pub number: String,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct NewPaperFull {
+    pub paper: NewPaper,
+    pub authors: Vec<NewAuthor>,
+    pub subjects: Vec<NewSubject>,
+    pub captions: Vec<NewCaption>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct NewScrapeRun {
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: chrono::DateTime<chrono::Utc>,
+    pub query: String,
+    pub pages_scraped: i32,
+    pub papers_found: i32,
+    pub papers_stored: i32,
+    pub papers_failed: i32,
+    pub bytes_downloaded: i64,
+    /// Number of failed papers by kind of failure
+    pub failures: HashMap<String, i32>,
+}
+
+/// Freshly fetched metadata of an existing paper
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct PaperMetadataUpdate {
+    pub title: String,
+    pub description: String,
+    pub status: PaperStatus,
+    pub authors: Vec<NewAuthor>,
+    pub subjects: Vec<NewSubject>,
+}
+
+/// Single difference found while updating paper metadata
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PaperChange {
+    /// One of `title`, `description`, `status`, `author` or `subject`
+    pub field: String,
+    /// `None` when value was added
+    pub old_value: Option<String>,
+    /// `None` when value was removed
+    pub new_value: Option<String>,
+}
+
+/// Criteria for selecting papers, all set criteria must match
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct PaperFilter {
+    pub ids: Option<Vec<Id>>,
+    pub fetched_before: Option<chrono::DateTime<chrono::Utc>>,
+    pub subject: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct PaperRef {
+    pub id: Id,
+    pub url: String,
+}
+
+/// Paper matching a search query, higher score is better
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct SearchHit {
+    pub paper_id: Id,
+    pub score: f32,
+}
+
+/// Paper was inserted or changed, see [`crate::db::PaperStore::changes_since`]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct ChangeEvent {
+    pub paper_id: Id,
+    pub change_seq: i64,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    /// Paper was taken down or deleted and no longer exists
+    pub deleted: bool,
+}
+
+/// Tombstone of a deleted paper
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct Takedown {
+    pub id: Id,
+    /// Id the paper had before it was deleted, 0 for tombstones restored from
+    /// a dump
+    pub paper_id: Id,
+    pub url: String,
+    pub arxiv_id: Option<ArxivId>,
+    pub reason: String,
+    pub taken_down_at: chrono::DateTime<chrono::Utc>,
+    pub change_seq: i64,
+}
+
+/// Tombstone of a paper deleted from another database, see
+/// [`crate::db::PaperStore::insert_takedowns`]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct NewTakedown {
+    pub url: String,
+    pub arxiv_id: Option<ArxivId>,
+    pub reason: String,
+    pub taken_down_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Problem left behind e.g. by an interrupted scrape, see
+/// [`crate::db::PaperStore::check_integrity`]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IntegrityIssue {
+    /// Author of no paper
+    OrphanAuthor { id: Id, name: String },
+    /// Subject of no paper
+    OrphanSubject { id: Id, name: String },
+    EmptyTitle { paper_id: Id, url: String },
+    EmptyBody { paper_id: Id, url: String },
+    /// Paper stored under another url of the same paper, e.g. on
+    /// `export.arxiv.org` instead of `arxiv.org`
+    DuplicatePaper {
+        paper_id: Id,
+        url: String,
+        original_id: Id,
+    },
+    /// Embedding computed by a model which is no longer used
+    StaleEmbedding { paper_id: Id, model: String },
+}